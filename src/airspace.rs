@@ -0,0 +1,436 @@
+use serde_json::Value;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::geofence::{from_local, polygon_contains, to_local};
+
+const METERS_PER_FOOT: f64 = 0.3048;
+const METERS_PER_NM: f64 = 1852_f64;
+// spacing in meters used when sampling a path segment against airspace boundaries
+const SAMPLE_SPACING: f64 = 10_f64;
+// step in degrees used to approximate arcs and circles
+const ARC_STEP: f64 = 5_f64;
+
+// AirspaceAltitude is an airspace floor or ceiling in meters
+#[derive(Clone, Copy, Debug)]
+pub struct AirspaceAltitude {
+    pub meters: f64,
+    // true when measured above ground rather than above mean sea level
+    pub agl: bool,
+}
+
+impl AirspaceAltitude {
+    // parse reads OpenAir altitudes such as SFC, 1500ft AMSL, 300m AGL, FL65 or UNL
+    fn parse(text: &str) -> Option<AirspaceAltitude> {
+        let text = text.trim().to_lowercase();
+        if text.is_empty() {
+            return None;
+        }
+        if text.starts_with("sfc") || text.starts_with("gnd") {
+            return Some(AirspaceAltitude {
+                meters: 0.0,
+                agl: true,
+            });
+        }
+        if text.starts_with("unl") {
+            return Some(AirspaceAltitude {
+                meters: f64::INFINITY,
+                agl: false,
+            });
+        }
+        if let Some(level) = text.strip_prefix("fl") {
+            let level: f64 = level.trim().parse().ok()?;
+            return Some(AirspaceAltitude {
+                meters: level * 100.0 * METERS_PER_FOOT,
+                agl: false,
+            });
+        }
+        let end = text
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(text.len());
+        let value: f64 = text[..end].parse().ok()?;
+        let rest = text[end..].trim();
+        let meters = if rest.starts_with('m') && !rest.starts_with("msl") {
+            value
+        } else {
+            value * METERS_PER_FOOT
+        };
+        let agl = rest.contains("agl") || rest.contains("gnd") || rest.contains("sfc");
+        Some(AirspaceAltitude { meters, agl })
+    }
+
+    // to_relative converts to meters above the home position
+    fn to_relative(self, home_elevation: f64) -> f64 {
+        if self.agl {
+            self.meters
+        } else {
+            self.meters - home_elevation
+        }
+    }
+}
+
+// Airspace is a single airspace volume with its lateral boundary as (lat, lon) vertices
+#[derive(Clone, Debug)]
+pub struct Airspace {
+    pub name: String,
+    pub class: String,
+    pub floor: AirspaceAltitude,
+    pub ceiling: AirspaceAltitude,
+    pub vertices: Vec<(f64, f64)>,
+}
+
+impl Airspace {
+    fn new(class: &str) -> Airspace {
+        Airspace {
+            name: String::new(),
+            class: class.to_string(),
+            floor: AirspaceAltitude {
+                meters: 0.0,
+                agl: true,
+            },
+            ceiling: AirspaceAltitude {
+                meters: f64::INFINITY,
+                agl: false,
+            },
+            vertices: vec![],
+        }
+    }
+
+    // is_restricted is true for airspace that must not be entered between its floor and ceiling
+    pub fn is_restricted(&self) -> bool {
+        matches!(self.class.as_str(), "R" | "P" | "Q")
+    }
+
+    // load_openair parses an OpenAir airspace file
+    pub fn load_openair(path: &Path) -> io::Result<Vec<Airspace>> {
+        let data = fs::read_to_string(path)?;
+        let invalid = |line: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid OpenAir line in {}: {}", path.display(), line),
+            )
+        };
+
+        let mut airspaces = vec![];
+        let mut current: Option<Airspace> = None;
+        let mut center = (0.0, 0.0);
+        let mut clockwise = true;
+        for line in data.lines() {
+            let line = line.split('*').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (record, value) = line.split_at(line.find(' ').unwrap_or(line.len()));
+            let value = value.trim();
+            if record == "AC" {
+                airspaces.extend(current.take());
+                current = Some(Airspace::new(value));
+                clockwise = true;
+                continue;
+            }
+            let airspace = match current.as_mut() {
+                Some(airspace) => airspace,
+                None => continue,
+            };
+            match record {
+                "AN" => airspace.name = value.to_string(),
+                "AL" => {
+                    airspace.floor = AirspaceAltitude::parse(value).ok_or_else(|| invalid(line))?
+                }
+                "AH" => {
+                    airspace.ceiling =
+                        AirspaceAltitude::parse(value).ok_or_else(|| invalid(line))?
+                }
+                "DP" => airspace
+                    .vertices
+                    .push(parse_coordinate(value).ok_or_else(|| invalid(line))?),
+                "V" => {
+                    if let Some(coordinate) = value.strip_prefix("X=") {
+                        center = parse_coordinate(coordinate).ok_or_else(|| invalid(line))?;
+                    } else if let Some(direction) = value.strip_prefix("D=") {
+                        clockwise = direction.trim() != "-";
+                    }
+                }
+                "DC" => {
+                    let radius: f64 = value.parse().map_err(|_| invalid(line))?;
+                    airspace
+                        .vertices
+                        .extend(arc(center, radius * METERS_PER_NM, 0.0, 360.0, true));
+                }
+                "DA" => {
+                    let parts: Vec<f64> = value
+                        .split(',')
+                        .map(|part| part.trim().parse())
+                        .collect::<Result<_, _>>()
+                        .map_err(|_| invalid(line))?;
+                    if parts.len() != 3 {
+                        return Err(invalid(line));
+                    }
+                    airspace.vertices.extend(arc(
+                        center,
+                        parts[0] * METERS_PER_NM,
+                        parts[1],
+                        parts[2],
+                        clockwise,
+                    ));
+                }
+                "DB" => {
+                    let mut parts = value.split(',');
+                    let start = parts
+                        .next()
+                        .and_then(parse_coordinate)
+                        .ok_or_else(|| invalid(line))?;
+                    let end = parts
+                        .next()
+                        .and_then(parse_coordinate)
+                        .ok_or_else(|| invalid(line))?;
+                    let (sx, sy) = to_local(center, start);
+                    let (ex, ey) = to_local(center, end);
+                    airspace.vertices.extend(arc(
+                        center,
+                        sx.hypot(sy),
+                        sx.atan2(sy).to_degrees(),
+                        ex.atan2(ey).to_degrees(),
+                        clockwise,
+                    ));
+                }
+                _ => {}
+            }
+        }
+        airspaces.extend(current.take());
+        Ok(airspaces)
+    }
+
+    // load_geojson parses polygons in a GeoJSON file as restricted areas
+    // the optional "name", "floor" and "ceiling" (meters AMSL) properties are read per feature
+    pub fn load_geojson(path: &Path) -> io::Result<Vec<Airspace>> {
+        let data = fs::read_to_string(path)?;
+        let value: Value = serde_json::from_str(data.as_str())?;
+        let features = match value["features"].as_array() {
+            Some(features) => features.clone(),
+            None => vec![value],
+        };
+
+        let mut airspaces = vec![];
+        for feature in features {
+            let geometry = &feature["geometry"];
+            let polygons = match geometry["type"].as_str() {
+                Some("Polygon") => vec![geometry["coordinates"].clone()],
+                Some("MultiPolygon") => geometry["coordinates"]
+                    .as_array()
+                    .cloned()
+                    .unwrap_or_default(),
+                _ => continue,
+            };
+            for polygon in polygons {
+                // only the outer ring bounds the area
+                let ring = match polygon[0].as_array() {
+                    Some(ring) => ring,
+                    None => continue,
+                };
+                let mut airspace = Airspace::new("R");
+                airspace.name = feature["properties"]["name"]
+                    .as_str()
+                    .unwrap_or("GeoJSON area")
+                    .to_string();
+                if let Some(floor) = feature["properties"]["floor"].as_f64() {
+                    airspace.floor = AirspaceAltitude {
+                        meters: floor,
+                        agl: false,
+                    };
+                }
+                if let Some(ceiling) = feature["properties"]["ceiling"].as_f64() {
+                    airspace.ceiling = AirspaceAltitude {
+                        meters: ceiling,
+                        agl: false,
+                    };
+                }
+                for position in ring {
+                    // GeoJSON positions are [lon, lat]
+                    if let (Some(lon), Some(lat)) = (position[0].as_f64(), position[1].as_f64()) {
+                        airspace.vertices.push((lat, lon));
+                    }
+                }
+                airspaces.push(airspace);
+            }
+        }
+        Ok(airspaces)
+    }
+}
+
+// check_path flags every waypoint leg of path, as (lat, lon, alt relative to home), that
+// enters an airspace between its floor and ceiling
+pub fn check_path(
+    airspaces: &[Airspace],
+    path: &[(f64, f64, f64)],
+    home_elevation: f64,
+) -> Vec<String> {
+    let mut flags = vec![];
+    for airspace in airspaces {
+        if airspace.class == "G" || airspace.vertices.len() < 3 {
+            continue;
+        }
+        let floor = airspace.floor.to_relative(home_elevation);
+        let ceiling = airspace.ceiling.to_relative(home_elevation);
+        for leg in 0..path.len() {
+            let from = path[leg];
+            let to = if leg + 1 < path.len() {
+                path[leg + 1]
+            } else {
+                from
+            };
+            let (dx, dy) = to_local((from.0, from.1), (to.0, to.1));
+            let samples = (dx.hypot(dy) / SAMPLE_SPACING).ceil().max(1.0) as usize;
+            let flagged = (0..=samples).any(|i| {
+                let t = i as f64 / samples as f64;
+                let (lat, lon) = from_local((from.0, from.1), (dx * t, dy * t));
+                let alt = from.2 + (to.2 - from.2) * t;
+                polygon_contains(&airspace.vertices, (lat, lon)) && alt >= floor && alt <= ceiling
+            });
+            if flagged {
+                let reason = if airspace.is_restricted() {
+                    "enters restricted airspace"
+                } else {
+                    "is above the airspace floor"
+                };
+                flags.push(format!(
+                    "Waypoint {} {} {} ({})",
+                    leg, reason, airspace.name, airspace.class
+                ));
+                break;
+            }
+        }
+    }
+    flags
+}
+
+// arc approximates an arc around center from start to end, as bearings in degrees from north
+fn arc(center: (f64, f64), radius: f64, start: f64, end: f64, clockwise: bool) -> Vec<(f64, f64)> {
+    let mut sweep = if clockwise { end - start } else { start - end };
+    while sweep <= 0.0 {
+        sweep += 360.0;
+    }
+    let steps = (sweep / ARC_STEP).ceil() as usize;
+    (0..=steps)
+        .map(|i| {
+            let delta = sweep * (i as f64) / (steps as f64);
+            let bearing = if clockwise {
+                start + delta
+            } else {
+                start - delta
+            }
+            .to_radians();
+            from_local(center, (radius * bearing.sin(), radius * bearing.cos()))
+        })
+        .collect()
+}
+
+// parse_coordinate reads OpenAir coordinates such as 51:30:00 N 000:10:30 W
+fn parse_coordinate(text: &str) -> Option<(f64, f64)> {
+    let text = text.trim().to_uppercase();
+    let lat_end = text.find(['N', 'S'])?;
+    let lon_end = text.find(['E', 'W'])?;
+    let mut lat = parse_degrees(&text[..lat_end])?;
+    // the longitude follows the latitude, text with E or W first is not a coordinate
+    let mut lon = parse_degrees(text.get(lat_end + 1..lon_end)?)?;
+    if &text[lat_end..lat_end + 1] == "S" {
+        lat = -lat;
+    }
+    if &text[lon_end..lon_end + 1] == "W" {
+        lon = -lon;
+    }
+    Some((lat, lon))
+}
+
+// parse_degrees reads dd:mm:ss, dd:mm.mm or dd.dd into decimal degrees
+fn parse_degrees(text: &str) -> Option<f64> {
+    let mut degrees = 0.0;
+    let mut scale = 1.0;
+    for part in text.trim().split(':') {
+        degrees += part.trim().parse::<f64>().ok()? / scale;
+        scale *= 60.0;
+    }
+    Some(degrees)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: (f64, f64), b: (f64, f64)) {
+        assert!(
+            (a.0 - b.0).abs() < 1e-9 && (a.1 - b.1).abs() < 1e-9,
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn parse_coordinate_reads_openair_formats() {
+        assert_near(
+            parse_coordinate("51:30:00 N 000:10:30 W").unwrap(),
+            (51.5, -0.175),
+        );
+        assert_near(
+            parse_coordinate("33:45.5S 151:12.0E").unwrap(),
+            (-33.7583333333, 151.2),
+        );
+        assert_near(parse_coordinate(" 47.25 n 8.5 e ").unwrap(), (47.25, 8.5));
+    }
+
+    #[test]
+    fn parse_coordinate_rejects_invalid_text() {
+        assert_eq!(parse_coordinate("000:10:30 W 51:30:00 N"), None);
+        assert_eq!(parse_coordinate("51:30:00 N"), None);
+        assert_eq!(parse_coordinate("51:30:00 NE"), None);
+        assert_eq!(parse_coordinate("51:xx:00 N 000:10:30 W"), None);
+    }
+
+    #[test]
+    fn altitudes_parse_to_meters() {
+        let sfc = AirspaceAltitude::parse("SFC").unwrap();
+        assert!(sfc.agl && sfc.meters == 0.0);
+        let fl = AirspaceAltitude::parse("FL65").unwrap();
+        assert!(!fl.agl && (fl.meters - 1981.2).abs() < 1e-9);
+        let amsl = AirspaceAltitude::parse("1500ft AMSL").unwrap();
+        assert!(!amsl.agl && (amsl.meters - 457.2).abs() < 1e-9);
+        let agl = AirspaceAltitude::parse("300m AGL").unwrap();
+        assert!(agl.agl && agl.meters == 300.0);
+        assert!(AirspaceAltitude::parse("UNL").unwrap().meters.is_infinite());
+        assert!(AirspaceAltitude::parse("").is_none());
+    }
+
+    #[test]
+    fn openair_restricted_area_flags_paths_inside_its_band() {
+        let path = std::env::temp_dir().join(format!("flightctl-{}.txt", std::process::id()));
+        fs::write(
+            &path,
+            "* test airspace\n\
+             AC R\n\
+             AN TEST AREA\n\
+             AL 100m AGL\n\
+             AH 200m AGL\n\
+             DP 47:00:00 N 008:00:00 E\n\
+             DP 47:00:00 N 008:01:00 E\n\
+             DP 47:01:00 N 008:01:00 E\n\
+             DP 47:01:00 N 008:00:00 E\n",
+        )
+        .unwrap();
+        let airspaces = Airspace::load_openair(&path);
+        fs::remove_file(&path).unwrap();
+        let airspaces = airspaces.unwrap();
+        assert_eq!(airspaces.len(), 1);
+        assert_eq!(airspaces[0].name, "TEST AREA");
+        assert!(airspaces[0].is_restricted());
+        assert_eq!(airspaces[0].vertices.len(), 4);
+
+        let crossing = [(46.99, 8.005, 150.0), (47.02, 8.005, 150.0)];
+        assert_eq!(check_path(&airspaces, &crossing, 0.0).len(), 1);
+        let below = [(46.99, 8.005, 50.0), (47.02, 8.005, 50.0)];
+        assert!(check_path(&airspaces, &below, 0.0).is_empty());
+        let beside = [(46.99, 8.05, 150.0), (47.02, 8.05, 150.0)];
+        assert!(check_path(&airspaces, &beside, 0.0).is_empty());
+    }
+}
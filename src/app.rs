@@ -1,7 +1,9 @@
 use std::error::Error;
 use std::io;
-use std::time::{Duration, Instant};
+use std::sync::Arc;
+use std::time::Duration;
 
+use crate::manifest::Manifest;
 use crate::run_mode::RunApp;
 use crossterm::event::{DisableMouseCapture, Event, KeyCode};
 use crossterm::terminal::{disable_raw_mode, LeaveAlternateScreen};
@@ -18,7 +20,7 @@ pub struct TabState<'a> {
 }
 
 impl<'a> TabState<'a> {
-    pub fn new(titles: Vec<&'a str>) -> TabState {
+    pub fn new(titles: Vec<&'a str>) -> TabState<'a> {
         TabState { titles, index: 0 }
     }
     pub fn next(&mut self) {
@@ -57,7 +59,7 @@ impl Default for App {
 
 pub async fn run(
     tick_rate: Duration,
-    data: Option<Vec<(String, String)>>,
+    manifest: Option<Manifest>,
     is_run_mode: bool,
 ) -> Result<(), Box<dyn Error>> {
     // prepare terminal
@@ -71,14 +73,14 @@ pub async fn run(
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    if is_run_mode {
-        let mut app = RunApp::new("FlightCTL",  true);
-        run_mode::run_app(&mut terminal, &mut app, data.unwrap(), tick_rate);
+    let result = if is_run_mode {
+        let app = Arc::new(RunApp::new("FlightCTL", true));
+        run_mode::run_app(&mut terminal, app, manifest.unwrap(), tick_rate).await
     } else {
         let app = App::default();
         // run app with UI still in progress
-        run_app(&mut terminal, app);
-    }
+        run_app(&mut terminal, app).await
+    };
 
     disable_raw_mode()?;
     execute!(
@@ -88,6 +90,8 @@ pub async fn run(
     )?;
     terminal.show_cursor()?;
 
+    // the terminal is restored before an error is reported
+    result?;
     Ok(())
 }
 
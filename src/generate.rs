@@ -1,9 +1,16 @@
+use crate::airspace::{check_path, Airspace};
+use crate::geofence::{from_local, to_local, Fence};
+use crate::mtsp;
+use crate::terrain::Terrain;
+use clap::ArgEnum;
 use serde_json::{json, Value};
 use std::f64::consts::PI;
+use std::fs;
 use std::fs::File;
 use std::io;
 use std::io::Write;
 use std::path::Path;
+use std::str::FromStr;
 
 // LineMission uses 3 drones to create a line shape with width (m) and angle (rad)
 pub struct LineMission {
@@ -21,6 +28,7 @@ pub struct LineMission {
 }
 
 impl LineMission {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         width: u8,
         angle: f64,
@@ -42,26 +50,30 @@ impl LineMission {
 }
 
 impl ShapeMission for LineMission {
+    fn formation_center(&self) -> Option<(f64, f64)> {
+        Some((self.target_location.lat, self.target_location.lon))
+    }
+
     fn generate_missions(&self) -> Vec<Vec<Point>> {
         let mut ret = vec![];
         let dy = (self.width / 2) as f64 * self.angle.sin();
         let dx = (self.width / 2) as f64 * self.angle.cos();
-        for i in vec![1_f64, -1_f64] {
+        for i in [1_f64, -1_f64] {
             // 1 degree lat = 111111 m
             let dlat_degree = dy * i / 111111_f64;
             // 1 degree lon = 111111 * cos(lat) m
             let dlon_degree = dx * i / (111111_f64 * (self.target_location.lon * TO_RAD).cos());
 
-            let mut mission = vec![];
-            mission.push(self.start.clone());
-            mission.push(self.target_location.clone());
-            mission.push(Point::new(
-                self.target_location.lat + dlat_degree,
-                self.target_location.lon + dlon_degree,
-                self.target_location.alt,
-                self.hold_sec,
-            ));
-            ret.push(mission);
+            ret.push(vec![
+                self.start.clone(),
+                self.target_location.clone(),
+                Point::new(
+                    self.target_location.lat + dlat_degree,
+                    self.target_location.lon + dlon_degree,
+                    self.target_location.alt,
+                    self.hold_sec,
+                ),
+            ]);
         }
         // push middle drone mission
         ret.push(vec![
@@ -111,9 +123,13 @@ impl SquareMission {
 }
 
 impl ShapeMission for SquareMission {
+    fn formation_center(&self) -> Option<(f64, f64)> {
+        Some((self.target_location.lat, self.target_location.lon))
+    }
+
     fn generate_missions(&self) -> Vec<Vec<Point>> {
         let mut ret = vec![];
-        for (mut dx, mut dy) in vec![(0.5, 0.5), (-0.5, 0.5), (-0.5, -0.5), (0.5, -0.5)] {
+        for (mut dx, mut dy) in [(0.5, 0.5), (-0.5, 0.5), (-0.5, -0.5), (0.5, -0.5)] {
             dy *= self.width as f64;
             dx *= self.width as f64;
 
@@ -122,16 +138,16 @@ impl ShapeMission for SquareMission {
             // 1 degree lon = 111111 * cos(lat) m
             let dlon_degree = dx / (111111_f64 * (self.target_location.lon * TO_RAD).cos());
 
-            let mut mission = vec![];
-            mission.push(self.start.clone());
-            mission.push(self.target_location.clone());
-            mission.push(Point::new(
-                self.target_location.lat + dlat_degree,
-                self.target_location.lon + dlon_degree,
-                self.target_location.alt,
-                self.hold_sec,
-            ));
-            ret.push(mission);
+            ret.push(vec![
+                self.start.clone(),
+                self.target_location.clone(),
+                Point::new(
+                    self.target_location.lat + dlat_degree,
+                    self.target_location.lon + dlon_degree,
+                    self.target_location.alt,
+                    self.hold_sec,
+                ),
+            ]);
         }
         ret
     }
@@ -139,6 +155,24 @@ impl ShapeMission for SquareMission {
 
 static TO_RAD: f64 = PI / 180_f64;
 
+// QGC altitude modes, mixed plans take the mode of each item
+const ALTITUDE_MODE_MIXED: u8 = 0;
+const ALTITUDE_MODE_RELATIVE: u8 = 1;
+const ALTITUDE_MODE_ABSOLUTE: u8 = 2;
+const ALTITUDE_MODE_CALC_ABOVE_TERRAIN: u8 = 3;
+// takeoff altitude used when a mission has no waypoint to take the altitude from
+const DEFAULT_TAKEOFF_ALT: u8 = 50;
+// minimum pitch in degrees for a fixed wing takeoff
+const FIXED_WING_TAKEOFF_PITCH: u8 = 15;
+// glide slope in degrees of the fixed wing landing approach
+const LANDING_GLIDE_SLOPE: f64 = 6_f64;
+// MAV_VTOL_STATE values for DO_VTOL_TRANSITION
+const VTOL_STATE_MC: u8 = 3;
+const VTOL_STATE_FW: u8 = 4;
+// MAVLink frames
+const FRAME_GLOBAL: u8 = 0;
+const FRAME_GLOBAL_RELATIVE_ALT: u8 = 3;
+
 // CircleMission uses n drones (count) to create a circle shape with radius (m)
 pub struct CircleMission {
     // radius is in meters
@@ -154,6 +188,7 @@ pub struct CircleMission {
 }
 
 impl CircleMission {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         count: u8,
         radius: u8,
@@ -175,6 +210,10 @@ impl CircleMission {
 }
 
 impl ShapeMission for CircleMission {
+    fn formation_center(&self) -> Option<(f64, f64)> {
+        Some((self.target_location.lat, self.target_location.lon))
+    }
+
     fn generate_missions(&self) -> Vec<Vec<Point>> {
         let mut ret = vec![];
 
@@ -189,27 +228,385 @@ impl ShapeMission for CircleMission {
             // 1 degree lon = 111111 * cos(lat) m
             let dlon_degree = dx_m / (111111_f64 * (self.target_location.lon * TO_RAD).cos());
 
-            let mut mission = vec![];
-            mission.push(self.start.clone());
-            mission.push(self.target_location.clone());
-            mission.push(Point::new(
-                self.target_location.lat + dlat_degree,
-                self.target_location.lon + dlon_degree,
-                self.target_location.alt,
-                self.hold_sec,
+            ret.push(vec![
+                self.start.clone(),
+                self.target_location.clone(),
+                Point::new(
+                    self.target_location.lat + dlat_degree,
+                    self.target_location.lon + dlon_degree,
+                    self.target_location.alt,
+                    self.hold_sec,
+                ),
+            ]);
+        }
+
+        ret
+    }
+}
+
+// SurveyLine is the (start, end) of a survey line in meters east and north of the survey origin
+type SurveyLine = ((f64, f64), (f64, f64));
+
+// SurveyMission splits a polygon between count drones, each flying a lawnmower pattern over
+// its share of the area
+pub struct SurveyMission {
+    // (lat, lon) vertices of the area to survey
+    polygon: Vec<(f64, f64)>,
+    count: u8,
+    // distance between survey lines in meters
+    spacing: f64,
+    // direction of the survey lines in degrees clockwise from north
+    heading: f64,
+    // start indicates the location the drone starts at
+    // altitude is ignored
+    start: Point,
+    alt: u8,
+    // distance in meters between camera triggers along the survey lines
+    trigger_distance: Option<f64>,
+}
+
+impl SurveyMission {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        polygon: Vec<(f64, f64)>,
+        count: u8,
+        spacing: f64,
+        heading: f64,
+        start_lat: f64,
+        start_lon: f64,
+        alt: u8,
+        trigger_distance: Option<f64>,
+    ) -> SurveyMission {
+        SurveyMission {
+            polygon,
+            count,
+            spacing,
+            heading,
+            start: Point::new(start_lat, start_lon, 0, 0),
+            alt,
+            trigger_distance,
+        }
+    }
+
+    // survey_lines returns the (start, end) local coordinates of every part of a line inside the
+    // polygon, lines run along heading and are ordered across the polygon one cell at a time,
+    // where a cell is a run of lines crossing the polygon the same number of times
+    fn survey_lines(&self, origin: (f64, f64)) -> Vec<SurveyLine> {
+        let heading = self.heading * TO_RAD;
+        // along is the line direction, across is perpendicular to it, both in (east, north)
+        let along = (heading.sin(), heading.cos());
+        let across = (heading.cos(), -heading.sin());
+        let vertices: Vec<(f64, f64)> = self
+            .polygon
+            .iter()
+            .map(|p| {
+                let (x, y) = to_local(origin, *p);
+                (x * across.0 + y * across.1, x * along.0 + y * along.1)
+            })
+            .collect();
+        let u_min = vertices.iter().map(|v| v.0).fold(f64::INFINITY, f64::min);
+        let u_max = vertices
+            .iter()
+            .map(|v| v.0)
+            .fold(f64::NEG_INFINITY, f64::max);
+
+        let mut cells: Vec<Vec<SurveyLine>> = vec![];
+        // cells still being extended, one per part of the previous line
+        let mut open: Vec<usize> = vec![];
+        let mut u = u_min + self.spacing / 2.0;
+        while u < u_max {
+            // the line is inside the polygon between each pair of edge crossings
+            let mut crossings = vec![];
+            for i in 0..vertices.len() {
+                let a = vertices[i];
+                let b = vertices[(i + 1) % vertices.len()];
+                if (a.0 <= u) != (b.0 <= u) {
+                    crossings.push(a.1 + (u - a.0) / (b.0 - a.0) * (b.1 - a.1));
+                }
+            }
+            crossings.sort_by(f64::total_cmp);
+            let to_xy = |v: f64| (u * across.0 + v * along.0, u * across.1 + v * along.1);
+            let parts: Vec<SurveyLine> = crossings
+                .chunks_exact(2)
+                .filter(|pair| pair[0] < pair[1])
+                .map(|pair| (to_xy(pair[0]), to_xy(pair[1])))
+                .collect();
+            // a change in the number of parts starts new cells, flying across the change
+            // would leave the polygon
+            if parts.len() != open.len() {
+                open = (cells.len()..cells.len() + parts.len()).collect();
+                cells.extend(parts.iter().map(|_| vec![]));
+            }
+            for (cell, part) in open.iter().zip(parts) {
+                cells[*cell].push(part);
+            }
+            u += self.spacing;
+        }
+        cells.into_iter().flatten().collect()
+    }
+
+    // strips splits the survey lines into count consecutive strips of about the same total
+    // line length, every strip gets a line when there are at least count lines
+    fn strips(&self, origin: (f64, f64)) -> Vec<Vec<SurveyLine>> {
+        let count = self.count as usize;
+        let lines = self.survey_lines(origin);
+        let length =
+            |line: &((f64, f64), (f64, f64))| (line.1 .0 - line.0 .0).hypot(line.1 .1 - line.0 .1);
+        let total: f64 = lines.iter().map(length).sum();
+        let mut strips: Vec<Vec<SurveyLine>> = vec![vec![]; count];
+        let mut strip = 0;
+        let mut flown = 0.0;
+        let line_count = lines.len();
+        for (i, line) in lines.into_iter().enumerate() {
+            let target = ((flown / total * count as f64) as usize).min(count - 1);
+            // move on once the strip has a line, without skipping a strip and early enough
+            // to leave a line for every strip after it
+            if !strips[strip].is_empty() && (target > strip || count - strip > line_count - i) {
+                strip += 1;
+            }
+            flown += length(&line);
+            strips[strip].push(line);
+        }
+        strips
+    }
+
+    // check_lines fails when the area has fewer survey lines than vehicles, leaving a vehicle
+    // with nothing to survey
+    pub fn check_lines(&self) -> io::Result<()> {
+        if self.polygon.len() < 3 || self.spacing <= 0.0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Survey needs a polygon of at least 3 vertices and a positive spacing",
+            ));
+        }
+        let lines = self.survey_lines(self.polygon[0]).len();
+        if lines < self.count as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Survey has {} lines for {} vehicles, reduce the spacing or the vehicles",
+                    lines, self.count
+                ),
             ));
+        }
+        Ok(())
+    }
+}
+
+impl ShapeMission for SurveyMission {
+    fn generate_missions(&self) -> Vec<Vec<Point>> {
+        let mut ret = vec![];
+        if self.polygon.len() < 3 || self.count == 0 || self.spacing <= 0.0 {
+            return ret;
+        }
+        let origin = self.polygon[0];
+        for strip in self.strips(origin) {
+            let mut mission = vec![self.start.clone()];
+            for (i, (a, b)) in strip.into_iter().enumerate() {
+                // alternate direction every line for the lawnmower pattern
+                let (first, second) = if i % 2 == 0 { (a, b) } else { (b, a) };
+                for (x, y) in [first, second] {
+                    let (lat, lon) = from_local(origin, (x, y));
+                    mission.push(Point::new(lat, lon, self.alt, 0));
+                }
+            }
+            if let Some(distance) = self.trigger_distance {
+                if mission.len() > 1 {
+                    mission[1]
+                        .actions
+                        .push(PayloadAction::TriggerDistance(distance));
+                    let last = mission.len() - 1;
+                    mission[last]
+                        .actions
+                        .push(PayloadAction::TriggerDistance(0.0));
+                }
+            }
             ret.push(mission);
         }
+        ret
+    }
+}
+
+// PointsMission distributes a list of points between count drones, ordering each drone's
+// points to keep the longest route as short as possible
+pub struct PointsMission {
+    // (lat, lon) of every point to visit
+    points: Vec<(f64, f64)>,
+    count: u8,
+    // start indicates the location the drone starts at
+    // altitude is ignored
+    start: Point,
+    alt: u8,
+    // number of sec to hold at each point
+    hold_sec: u8,
+    // longest route in meters a single drone can fly, including the return to start
+    endurance: Option<f64>,
+}
 
+impl PointsMission {
+    pub fn new(
+        points: Vec<(f64, f64)>,
+        count: u8,
+        start_lat: f64,
+        start_lon: f64,
+        alt: u8,
+        hold_sec: u8,
+        endurance: Option<f64>,
+    ) -> PointsMission {
+        PointsMission {
+            points,
+            count,
+            start: Point::new(start_lat, start_lon, 0, 0),
+            alt,
+            hold_sec,
+            endurance,
+        }
+    }
+
+    // routes returns the ordered point indices and route length in meters of every drone
+    fn routes(&self) -> Vec<(Vec<usize>, f64)> {
+        let origin = (self.start.lat, self.start.lon);
+        let local: Vec<(f64, f64)> = self.points.iter().map(|p| to_local(origin, *p)).collect();
+        mtsp::distribute((0.0, 0.0), &local, self.count as usize, self.endurance)
+            .into_iter()
+            .map(|route| {
+                let length = mtsp::route_length((0.0, 0.0), &local, &route);
+                (route, length)
+            })
+            .collect()
+    }
+
+    // check_endurance fails when any drone's route is longer than the endurance limit
+    pub fn check_endurance(&self) -> io::Result<()> {
+        let endurance = match self.endurance {
+            Some(endurance) => endurance,
+            None => return Ok(()),
+        };
+        for (i, (_, length)) in self.routes().iter().enumerate() {
+            println!("Vehicle {} route is {:.0} meters", i, length);
+            if *length > endurance {
+                return Err(io::Error::other(format!(
+                    "Vehicle {} route of {:.0} meters exceeds the {:.0} meter endurance, add vehicles",
+                    i, length, endurance
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ShapeMission for PointsMission {
+    fn generate_missions(&self) -> Vec<Vec<Point>> {
+        let mut ret = vec![];
+        for (route, _) in self.routes() {
+            let mut mission = vec![self.start.clone()];
+            for i in route {
+                let (lat, lon) = self.points[i];
+                mission.push(Point::new(lat, lon, self.alt, self.hold_sec));
+            }
+            ret.push(mission);
+        }
         ret
     }
 }
 
-struct Point {
+// InspectionMission orbits count drones around a point or vertical structure, spread evenly
+// around the orbit, with the camera pointed at the structure on every altitude ring
+pub struct InspectionMission {
+    // center of the structure, altitude is ignored
+    center: Point,
+    // orbit radius in meters
+    radius: u8,
+    count: u8,
+    // altitude of every orbit ring, flown lowest first
+    ring_alts: Vec<u8>,
+    // number of waypoints around each ring
+    ring_points: u8,
+    // start indicates the location the drone starts at
+    // altitude is ignored
+    start: Point,
+}
+
+impl InspectionMission {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        count: u8,
+        radius: u8,
+        ring_alts: Vec<u8>,
+        ring_points: u8,
+        start_lat: f64,
+        start_lon: f64,
+        target_lat: f64,
+        target_lon: f64,
+    ) -> InspectionMission {
+        let mut ring_alts = ring_alts;
+        ring_alts.sort_unstable();
+        InspectionMission {
+            center: Point::new(target_lat, target_lon, 0, 0),
+            radius,
+            count,
+            ring_alts,
+            ring_points: ring_points.max(3),
+            start: Point::new(start_lat, start_lon, 0, 0),
+        }
+    }
+}
+
+impl ShapeMission for InspectionMission {
+    fn formation_center(&self) -> Option<(f64, f64)> {
+        Some((self.center.lat, self.center.lon))
+    }
+
+    fn generate_missions(&self) -> Vec<Vec<Point>> {
+        let mut ret = vec![];
+        let center = (self.center.lat, self.center.lon);
+        let step = 2.0 * PI / (self.ring_points as f64);
+        for i in 0..self.count {
+            // each drone starts its orbit at its own phase so they stay spread around the ring
+            let phase = 2.0 * PI * (i as f64) / (self.count as f64);
+            let mut mission = vec![self.start.clone()];
+            for alt in &self.ring_alts {
+                for j in 0..=self.ring_points {
+                    let rad = phase + step * (j as f64);
+                    let (lat, lon) = from_local(
+                        center,
+                        (
+                            (self.radius as f64) * rad.cos(),
+                            (self.radius as f64) * rad.sin(),
+                        ),
+                    );
+                    let mut point = Point::new(lat, lon, *alt, 0);
+                    if j == 0 {
+                        // look at the structure level with the ring
+                        point.roi = Some((center.0, center.1, *alt));
+                    }
+                    mission.push(point);
+                }
+            }
+            ret.push(mission);
+        }
+        ret
+    }
+}
+
+pub struct Point {
     lat: f64,
     lon: f64,
     alt: u8,
     hold_sec: u8,
+    // altitude in meters AMSL when the point is held at alt above the terrain below it
+    terrain_alt: Option<f64>,
+    // payload commands run on arrival, during the hold when the point has one
+    actions: Vec<PayloadAction>,
+    // (lat, lon, alt) region of interest the vehicle and camera face from arrival on
+    roi: Option<(f64, f64, u8)>,
+    // yaw in degrees clockwise from north held at the waypoint, free when unset
+    heading: Option<f64>,
+    // distance in meters at which the waypoint counts as reached
+    acceptance_radius: Option<f64>,
+    // radius in meters to pass by the waypoint instead of through it, negative passes
+    // counter clockwise
+    pass_radius: Option<f64>,
 }
 
 impl Point {
@@ -219,13 +616,557 @@ impl Point {
             lon,
             alt,
             hold_sec,
+            terrain_alt: None,
+            actions: vec![],
+            roi: None,
+            heading: None,
+            acceptance_radius: None,
+            pass_radius: None,
         }
     }
 }
 
 impl Clone for Point {
     fn clone(&self) -> Self {
-        Point::new(self.lat, self.lon, self.alt, self.hold_sec)
+        Point {
+            terrain_alt: self.terrain_alt,
+            actions: self.actions.clone(),
+            roi: self.roi,
+            heading: self.heading,
+            acceptance_radius: self.acceptance_radius,
+            pass_radius: self.pass_radius,
+            ..Point::new(self.lat, self.lon, self.alt, self.hold_sec)
+        }
+    }
+}
+
+// PayloadAction is a payload command run at a waypoint
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PayloadAction {
+    // DO_SET_SERVO with the servo output and its PWM in microseconds
+    Servo(u8, u16),
+    // DO_SET_RELAY with the relay and whether it is switched on
+    Relay(u8, bool),
+    // DO_GRIPPER with the gripper and whether it releases rather than grabs
+    Gripper(u8, bool),
+    // DO_DIGICAM_CONTROL taking a single photo
+    Digicam,
+    // IMAGE_START_CAPTURE with the interval in seconds and image count, 0 images runs until
+    // stopped
+    ImageCapture(f64, u16),
+    // DO_SET_CAM_TRIGG_DIST with the distance in meters, 0 stops triggering
+    TriggerDistance(f64),
+}
+
+impl FromStr for PayloadAction {
+    type Err = String;
+
+    // from_str reads actions such as servo:9:1900, relay:0:on, gripper:1:release, digicam,
+    // capture:2:10 or trigger:25
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid payload action {}", text);
+        let parts: Vec<&str> = text.split(':').map(|part| part.trim()).collect();
+        // integer fields are parsed as their own type, values out of its range are invalid
+        fn field<T: FromStr>(parts: &[&str], i: usize) -> Option<T> {
+            parts.get(i).and_then(|part| part.parse().ok())
+        }
+        let number = |i: usize| field::<f64>(&parts, i).ok_or_else(invalid);
+        let number_u8 = |i: usize| field::<u8>(&parts, i).ok_or_else(invalid);
+        let number_u16 = |i: usize| field::<u16>(&parts, i).ok_or_else(invalid);
+        let action = match (parts[0].to_lowercase().as_str(), parts.len()) {
+            ("servo", 3) => PayloadAction::Servo(number_u8(1)?, number_u16(2)?),
+            ("relay", 3) => match parts[2] {
+                "on" | "1" => PayloadAction::Relay(number_u8(1)?, true),
+                "off" | "0" => PayloadAction::Relay(number_u8(1)?, false),
+                _ => return Err(invalid()),
+            },
+            ("gripper", 3) => match parts[2] {
+                "release" => PayloadAction::Gripper(number_u8(1)?, true),
+                "grab" => PayloadAction::Gripper(number_u8(1)?, false),
+                _ => return Err(invalid()),
+            },
+            ("digicam", 1) => PayloadAction::Digicam,
+            ("capture", 3) => PayloadAction::ImageCapture(number(1)?, number_u16(2)?),
+            ("trigger", 2) => PayloadAction::TriggerDistance(number(1)?),
+            _ => return Err(invalid()),
+        };
+        Ok(action)
+    }
+}
+
+// Firmware is the autopilot the plan is generated for
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Firmware {
+    Px4,
+    Ardupilot,
+}
+
+impl Firmware {
+    // mav_autopilot is the MAV_AUTOPILOT value QGC stores as the plan's firmwareType
+    fn mav_autopilot(self) -> u8 {
+        match self {
+            Firmware::Px4 => 12,
+            Firmware::Ardupilot => 3,
+        }
+    }
+}
+
+// VehicleType is the airframe the plan is generated for
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq)]
+pub enum VehicleType {
+    Multirotor,
+    FixedWing,
+    Vtol,
+}
+
+impl VehicleType {
+    // flies_fixed_wing is true for airframes that cruise on a wing and cannot hover in place
+    fn flies_fixed_wing(self) -> bool {
+        matches!(self, VehicleType::FixedWing | VehicleType::Vtol)
+    }
+
+    // mav_type is the MAV_TYPE value QGC stores as the plan's vehicleType
+    fn mav_type(self) -> u8 {
+        match self {
+            VehicleType::Multirotor => 2,
+            VehicleType::FixedWing => 1,
+            VehicleType::Vtol => 22,
+        }
+    }
+}
+
+// AltitudeMode sets what generated altitudes are measured from
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq)]
+pub enum AltitudeMode {
+    // above the home position
+    Relative,
+    // above mean sea level, home_elevation is added to every altitude
+    Amsl,
+}
+
+// PlanProfile holds the plan header and item defaults for a fleet
+pub struct PlanProfile {
+    // speeds in m/s
+    pub cruise_speed: f64,
+    pub hover_speed: f64,
+    pub firmware: Firmware,
+    pub vehicle_type: VehicleType,
+    pub altitude_mode: AltitudeMode,
+    // takeoff altitude, the altitude of the first waypoint when unset
+    pub takeoff_alt: Option<u8>,
+}
+
+impl Default for PlanProfile {
+    fn default() -> Self {
+        PlanProfile {
+            cruise_speed: 15.0,
+            hover_speed: 5.0,
+            firmware: Firmware::Px4,
+            vehicle_type: VehicleType::Multirotor,
+            altitude_mode: AltitudeMode::Relative,
+            takeoff_alt: None,
+        }
+    }
+}
+
+impl PlanProfile {
+    // load reads a json profile, keys left out keep their default
+    // {"cruise_speed": 15, "hover_speed": 5, "firmware": "px4", "vehicle_type": "multirotor",
+    //  "altitude_mode": "relative", "takeoff_alt": 30}
+    pub fn load(path: &Path) -> io::Result<PlanProfile> {
+        let data = fs::read_to_string(path)?;
+        let value: Value = serde_json::from_str(data.as_str())?;
+        let invalid = |key: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid {} in profile {}", key, path.display()),
+            )
+        };
+        let mut profile = PlanProfile::default();
+        if let Some(speed) = value.get("cruise_speed") {
+            profile.cruise_speed = speed.as_f64().ok_or_else(|| invalid("cruise_speed"))?;
+        }
+        if let Some(speed) = value.get("hover_speed") {
+            profile.hover_speed = speed.as_f64().ok_or_else(|| invalid("hover_speed"))?;
+        }
+        if let Some(firmware) = value.get("firmware") {
+            profile.firmware = firmware
+                .as_str()
+                .and_then(|f| Firmware::from_str(f, true).ok())
+                .ok_or_else(|| invalid("firmware"))?;
+        }
+        if let Some(vehicle_type) = value.get("vehicle_type") {
+            profile.vehicle_type = vehicle_type
+                .as_str()
+                .and_then(|v| VehicleType::from_str(v, true).ok())
+                .ok_or_else(|| invalid("vehicle_type"))?;
+        }
+        if let Some(altitude_mode) = value.get("altitude_mode") {
+            profile.altitude_mode = altitude_mode
+                .as_str()
+                .and_then(|a| AltitudeMode::from_str(a, true).ok())
+                .ok_or_else(|| invalid("altitude_mode"))?;
+        }
+        if let Some(alt) = value.get("takeoff_alt") {
+            profile.takeoff_alt = Some(
+                alt.as_u64()
+                    .and_then(|a| u8::try_from(a).ok())
+                    .ok_or_else(|| invalid("takeoff_alt"))?,
+            );
+        }
+        Ok(profile)
+    }
+}
+
+// ItemAltitude holds the altitude fields of a mission item
+struct ItemAltitude {
+    mode: u8,
+    frame: u8,
+    // altitude as shown in QGC for the mode
+    altitude: f64,
+    amsl_above_terrain: Option<f64>,
+    // altitude sent to the vehicle in the item's frame
+    param: f64,
+    // meters above the ground the item is flown at, whatever the mode
+    height: f64,
+}
+
+// Facing sets the heading of the vehicles at their waypoints
+#[derive(Default)]
+pub enum Facing {
+    // heading left to the autopilot
+    #[default]
+    Free,
+    // face the center of the formation
+    Center,
+    // face a fixed heading in degrees clockwise from north, such as toward the audience
+    Heading(f64),
+}
+
+// LandingSchedule lands each vehicle on its own pad, with vehicles holding over their pad at
+// stepped altitudes and for staggered times so they do not descend together
+#[derive(Default)]
+pub struct LandingSchedule {
+    // landing pad of each vehicle in plan order, vehicles without one land at their launch point
+    pub pads: Vec<(f64, f64)>,
+    // seconds each vehicle holds over its pad longer than the vehicle before it
+    pub interval_sec: u16,
+    // meters each vehicle holds over its pad higher than the vehicle before it
+    pub alt_step: u8,
+}
+
+impl LandingSchedule {
+    fn pad(&self, vehicle: usize, launch: &Point) -> (f64, f64) {
+        self.pads
+            .get(vehicle)
+            .copied()
+            .unwrap_or((launch.lat, launch.lon))
+    }
+
+    // slot returns the holding point over the vehicle's pad and how long to hold there
+    fn slot(&self, vehicle: usize, launch: &Point, base_alt: u8) -> (Point, u32) {
+        let (lat, lon) = self.pad(vehicle, launch);
+        let alt = base_alt as usize + self.alt_step as usize * vehicle;
+        if alt > u8::MAX as usize {
+            println!(
+                "Landing altitude of vehicle {} is capped at {} m",
+                vehicle,
+                u8::MAX
+            );
+        }
+        let delay = self.interval_sec as u32 * vehicle as u32;
+        (
+            Point::new(lat, lon, alt.min(u8::MAX as usize) as u8, 0),
+            delay,
+        )
+    }
+}
+
+// PlanOptions holds the settings applied to every plan a ShapeMission writes
+#[derive(Default)]
+pub struct PlanOptions {
+    // buffer in meters around the planned paths for the inclusion geofence
+    // no inclusion geofence is generated when unset
+    pub fence_margin: Option<f64>,
+    // generate one inclusion geofence covering every vehicle instead of one per vehicle
+    pub shared_fence: bool,
+    // exclusion zones added to every plan
+    pub exclusions: Fence,
+    // add the launch point of every vehicle as a rally point
+    pub rally: bool,
+    // airspace each mission is checked against before it is written
+    pub airspaces: Vec<Airspace>,
+    // elevation of the launch point in meters AMSL, used against AMSL airspace floors
+    pub home_elevation: f64,
+    // local DEM tiles used for terrain following and clearance checks
+    pub terrain: Option<Terrain>,
+    // hold each waypoint's altitude above the terrain below it instead of above home
+    pub terrain_follow: bool,
+    // flag paths that come closer than this many meters to the terrain
+    pub min_clearance: Option<f64>,
+    // heading of every waypoint after the start
+    pub facing: Facing,
+    // acceptance radius in meters of waypoints that do not set their own
+    pub acceptance_radius: Option<f64>,
+    // pass radius in meters of waypoints that do not set their own
+    pub pass_radius: Option<f64>,
+    // plan header and item defaults
+    pub profile: PlanProfile,
+    // fixed wing and VTOL turn radius in meters, used for loiters and to lead turns
+    pub turn_radius: f64,
+    // fly fixed wing holds as whole loiter turns instead of loitering for the hold time
+    pub loiter_turns: bool,
+    // heading in degrees clockwise from north the fixed wing landing approach is flown on
+    pub land_heading: f64,
+    // land every vehicle on its own pad in turn instead of returning to launch together
+    pub landing: Option<LandingSchedule>,
+    // return altitude in meters of the first vehicle, written to the plan for run mode to set
+    pub return_alt: Option<u8>,
+    // meters each vehicle returns higher than the vehicle before it
+    pub return_alt_step: u8,
+    // fly home through explicit waypoints at the vehicle's return altitude
+    pub return_path: bool,
+    // fly the waypoints from repeat_from to the last one this many more times with DO_JUMP
+    pub repeat: u16,
+    // index of the first repeated waypoint, the takeoff is waypoint 0
+    pub repeat_from: usize,
+    // payload actions run at every waypoint after the takeoff
+    pub waypoint_actions: Vec<PayloadAction>,
+    // payload actions run at the last waypoint of every vehicle, its slot in the shape
+    pub slot_actions: Vec<PayloadAction>,
+}
+
+impl PlanOptions {
+    fn fence_for<'a>(&self, points: impl Iterator<Item = &'a Point>) -> Fence {
+        let mut fence = match self.fence_margin {
+            Some(margin) => {
+                let coords: Vec<(f64, f64)> = points.map(|p| (p.lat, p.lon)).collect();
+                Fence::inclusion_hull(&coords, margin)
+            }
+            None => Fence::default(),
+        };
+        fence.extend(&self.exclusions);
+        fence
+    }
+
+    // vehicle_return_alt is the return altitude of a vehicle, stepped up per vehicle
+    fn vehicle_return_alt(&self, vehicle: usize) -> Option<u8> {
+        let alt = self.return_alt? as usize + self.return_alt_step as usize * vehicle;
+        if alt > u8::MAX as usize {
+            println!(
+                "Return altitude of vehicle {} is capped at {} m",
+                vehicle,
+                u8::MAX
+            );
+        }
+        Some(alt.min(u8::MAX as usize) as u8)
+    }
+
+    // landing_pads returns the landing pad of every mission that has one
+    fn landing_pads(&self, missions: &[Vec<Point>]) -> Vec<Point> {
+        let schedule = match &self.landing {
+            Some(schedule) => schedule,
+            None => return vec![],
+        };
+        missions
+            .iter()
+            .enumerate()
+            .filter_map(|(i, mission)| {
+                let (lat, lon) = schedule.pad(i, mission.first()?);
+                Some(Point::new(lat, lon, 0, 0))
+            })
+            .collect()
+    }
+
+    fn rally_points(&self, missions: &[Vec<Point>]) -> Vec<Point> {
+        let mut points: Vec<Point> = vec![];
+        if !self.rally {
+            return points;
+        }
+        for mission in missions {
+            // rally at the launch point, at the altitude the vehicle flies the mission
+            let (start, alt) = match (mission.first(), mission.get(1)) {
+                (Some(start), Some(next)) => (start, next.alt),
+                (Some(start), None) => (start, start.alt),
+                _ => continue,
+            };
+            if !points
+                .iter()
+                .any(|p| p.lat == start.lat && p.lon == start.lon)
+            {
+                points.push(Point::new(start.lat, start.lon, alt, 0));
+            }
+        }
+        points
+    }
+
+    // item_altitude converts an altitude above home into the item fields for the altitude mode
+    // terrain following points are always calculated above terrain
+    fn item_altitude(&self, alt: u8, terrain_alt: Option<f64>) -> ItemAltitude {
+        let alt = alt as f64;
+        match (terrain_alt, self.profile.altitude_mode) {
+            (Some(amsl), _) => ItemAltitude {
+                mode: ALTITUDE_MODE_CALC_ABOVE_TERRAIN,
+                frame: FRAME_GLOBAL,
+                altitude: alt,
+                amsl_above_terrain: Some(amsl),
+                param: amsl,
+                height: alt,
+            },
+            (None, AltitudeMode::Amsl) => ItemAltitude {
+                mode: ALTITUDE_MODE_ABSOLUTE,
+                frame: FRAME_GLOBAL,
+                altitude: self.home_elevation + alt,
+                amsl_above_terrain: None,
+                param: self.home_elevation + alt,
+                height: alt,
+            },
+            (None, AltitudeMode::Relative) => ItemAltitude {
+                mode: ALTITUDE_MODE_RELATIVE,
+                frame: FRAME_GLOBAL_RELATIVE_ALT,
+                altitude: alt,
+                amsl_above_terrain: None,
+                param: alt,
+                height: alt,
+            },
+        }
+    }
+
+    // apply_waypoint_options sets the heading, acceptance and pass radius of every waypoint
+    // after the start, waypoints keep any value the shape already set
+    fn apply_waypoint_options(&self, mission: &mut [Point], center: Option<(f64, f64)>) {
+        for point in mission.iter_mut().skip(1) {
+            if point.heading.is_none() {
+                point.heading = match (&self.facing, center) {
+                    (Facing::Heading(heading), _) => Some(*heading),
+                    (Facing::Center, Some(center)) => {
+                        let (dx, dy) = to_local((point.lat, point.lon), center);
+                        // the center itself has no heading to face
+                        if dx.hypot(dy) < 1.0 {
+                            None
+                        } else {
+                            Some((dx.atan2(dy).to_degrees() + 360.0) % 360.0)
+                        }
+                    }
+                    _ => None,
+                };
+            }
+            point.acceptance_radius = point.acceptance_radius.or(self.acceptance_radius);
+            point.pass_radius = point.pass_radius.or(self.pass_radius);
+            point.actions.extend(self.waypoint_actions.iter().copied());
+        }
+        // the last waypoint is the vehicle's slot in the shape
+        if mission.len() > 1 {
+            let slot = mission.len() - 1;
+            mission[slot]
+                .actions
+                .extend(self.slot_actions.iter().copied());
+        }
+    }
+
+    // lead_turns sets the acceptance radius of fixed wing waypoints so each turn starts early
+    // enough to be flown at turn_radius, and warns about legs too short to turn between
+    fn lead_turns(&self, mission: &mut [Point]) {
+        if !self.profile.vehicle_type.flies_fixed_wing() || self.turn_radius <= 0.0 {
+            return;
+        }
+        for i in 1..mission.len() {
+            let (dx, dy) = to_local(
+                (mission[i - 1].lat, mission[i - 1].lon),
+                (mission[i].lat, mission[i].lon),
+            );
+            if i > 1 && dx.hypot(dy) < 2.0 * self.turn_radius {
+                println!(
+                    "Leg to waypoint {} is {:.0} m, shorter than the {:.0} m turn diameter",
+                    i,
+                    dx.hypot(dy),
+                    2.0 * self.turn_radius
+                );
+            }
+            if i + 1 >= mission.len() || mission[i].acceptance_radius.is_some() {
+                continue;
+            }
+            let (nx, ny) = to_local(
+                (mission[i].lat, mission[i].lon),
+                (mission[i + 1].lat, mission[i + 1].lon),
+            );
+            // the turn starts turn_radius * tan(turn / 2) before the waypoint
+            let turn = ((dx * nx + dy * ny) / (dx.hypot(dy) * nx.hypot(ny)))
+                .clamp(-1.0, 1.0)
+                .acos();
+            if turn.is_finite() {
+                let lead = self.turn_radius * (turn / 2.0).tan().min(2.0);
+                mission[i].acceptance_radius = Some(lead.max(1.0));
+            }
+        }
+    }
+
+    // follow_terrain sets the AMSL altitude of every waypoint after the start so it flies at
+    // its altitude above the terrain below it
+    fn follow_terrain(&self, mission: &mut [Point]) {
+        let terrain = match (&self.terrain, self.terrain_follow) {
+            (Some(terrain), true) => terrain,
+            _ => return,
+        };
+        for point in mission.iter_mut().skip(1) {
+            match terrain.elevation(point.lat, point.lon) {
+                Some(ground) => point.terrain_alt = Some(ground + point.alt as f64),
+                None => println!(
+                    "No terrain data at {:.7}, {:.7}, keeping altitude relative to home",
+                    point.lat, point.lon
+                ),
+            }
+        }
+    }
+
+    // check_clearance samples every leg against the terrain, from the takeoff above the launch
+    // point on
+    fn check_clearance(&self, mission: &[Point]) -> Vec<String> {
+        let mut flags = vec![];
+        let (terrain, min_clearance) = match (&self.terrain, self.min_clearance) {
+            (Some(terrain), Some(min_clearance)) => (terrain, min_clearance),
+            _ => return flags,
+        };
+        let home_ground = match mission.first() {
+            Some(start) => terrain.elevation(start.lat, start.lon),
+            None => return flags,
+        };
+        // the vehicle climbs to the takeoff altitude above launch before its first leg
+        let takeoff_alt = self
+            .profile
+            .takeoff_alt
+            .or(mission.get(1).map(|p| p.alt))
+            .unwrap_or(DEFAULT_TAKEOFF_ALT);
+        let amsl = |i: usize, p: &Point| {
+            let alt = if i == 0 { takeoff_alt } else { p.alt };
+            p.terrain_alt.or(home_ground.map(|g| g + alt as f64))
+        };
+        for leg in 0..mission.len() {
+            let from = &mission[leg];
+            let to = mission.get(leg + 1).unwrap_or(from);
+            let (from_alt, to_alt) = match (amsl(leg, from), amsl(leg + 1, to)) {
+                (Some(from_alt), Some(to_alt)) => (from_alt, to_alt),
+                _ => continue,
+            };
+            let (dx, dy) = to_local((from.lat, from.lon), (to.lat, to.lon));
+            let samples = (dx.hypot(dy) / 10.0).ceil().max(1.0) as usize;
+            for i in 0..=samples {
+                let t = i as f64 / samples as f64;
+                let (lat, lon) = from_local((from.lat, from.lon), (dx * t, dy * t));
+                let clearance = match terrain.elevation(lat, lon) {
+                    Some(ground) => from_alt + (to_alt - from_alt) * t - ground,
+                    None => continue,
+                };
+                if clearance < min_clearance {
+                    flags.push(format!(
+                        "Waypoint {} has {:.1} m terrain clearance at {:.7}, {:.7}",
+                        leg, clearance, lat, lon
+                    ));
+                    break;
+                }
+            }
+        }
+        flags
     }
 }
 
@@ -233,31 +1174,225 @@ pub trait ShapeMission {
     // generate missions creates a series missions made a list of of lat, lon coordinate tuples
     // the first tuple must be the starting location
     fn generate_missions(&self) -> Vec<Vec<Point>>;
-    fn write_mission_to_disk(&self, save_dir: &Path) -> io::Result<()> {
+    // formation_center is the point vehicles face with Facing::Center
+    fn formation_center(&self) -> Option<(f64, f64)> {
+        None
+    }
+    fn write_mission_to_disk(&self, save_dir: &Path, options: &PlanOptions) -> io::Result<()> {
         if !save_dir.is_dir() {
-            io::Error::new(io::ErrorKind::Other, "Not a directory");
+            return Err(io::Error::other("Not a directory"));
         }
-        let mut i = 0;
-        for mission in self.generate_missions() {
+        for (i, plan) in self.generate_plans(options)?.into_iter().enumerate() {
             let plan_path = save_dir.join(format!("plan_{}.plan", i));
-            let mut file = File::create(plan_path)?;
-
             println!("Writing plan {} to file {}", i, plan_path.display());
-            file.write_all(self.generate_plan(mission).as_bytes())?;
-            i += 1;
+            let mut file = File::create(&plan_path)?;
+            file.write_all(plan.as_bytes())?;
         }
         Ok(())
     }
-    fn generate_plan(&self, waypoints: Vec<Point>) -> String {
-        let mut plan = new_qgc_plan();
+    // generate_plans creates the QGC plan of every vehicle in memory, printing any airspace or
+    // terrain flags
+    fn generate_plans(&self, options: &PlanOptions) -> io::Result<Vec<String>> {
+        let missions = self.generate_missions();
+        let rally = options.rally_points(&missions);
+        let pads = options.landing_pads(&missions);
+        if let Some(schedule) = &options.landing {
+            if !schedule.pads.is_empty() && schedule.pads.len() < missions.len() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "{} landing pads given for {} vehicles",
+                        schedule.pads.len(),
+                        missions.len()
+                    ),
+                ));
+            }
+        }
+        let shared_fence = if options.shared_fence {
+            Some(options.fence_for(missions.iter().flatten().chain(pads.iter())))
+        } else {
+            None
+        };
+        let mut plans = vec![];
+        for (i, mut mission) in missions.into_iter().enumerate() {
+            options.apply_waypoint_options(&mut mission, self.formation_center());
+            options.lead_turns(&mut mission);
+            options.follow_terrain(&mut mission);
+            let fence = match &shared_fence {
+                Some(fence) => fence.clone(),
+                None => options.fence_for(mission.iter().chain(pads.get(i))),
+            };
 
-        for i in 0..(waypoints.len() + 1) {
+            let path: Vec<(f64, f64, f64)> = mission
+                .iter()
+                .map(|p| (p.lat, p.lon, p.alt as f64))
+                .collect();
+            for flag in check_path(&options.airspaces, &path, options.home_elevation) {
+                println!("Plan {} flagged: {}", i, flag);
+            }
+            for flag in options.check_clearance(&mission) {
+                println!("Plan {} flagged: {}", i, flag);
+            }
+
+            plans.push(self.generate_plan(i, mission, &fence, &rally, options));
+        }
+        Ok(plans)
+    }
+    fn generate_plan(
+        &self,
+        vehicle: usize,
+        waypoints: Vec<Point>,
+        fence: &Fence,
+        rally: &[Point],
+        options: &PlanOptions,
+    ) -> String {
+        let mut plan = new_qgc_plan(&options.profile, waypoints.first(), options.home_elevation);
+
+        let takeoff_alt = options
+            .profile
+            .takeoff_alt
+            .or(waypoints.get(1).map(|p| p.alt))
+            .unwrap_or(DEFAULT_TAKEOFF_ALT);
+        let mut items = vec![];
+        let mut jump_id = 1;
+        let ardupilot = options.profile.firmware == Firmware::Ardupilot;
+        let vehicle_type = options.profile.vehicle_type;
+        // doJumpId of the first item of each waypoint, the targets for DO_JUMP
+        let mut waypoint_ids = vec![];
+        for (i, waypoint) in waypoints.iter().enumerate() {
+            waypoint_ids.push(jump_id);
             if i == 0 {
-                plan["mission"]["items"][i] = new_takeoff(waypoints[i].clone());
-            } else if i == waypoints.len() {
-                plan["mission"]["items"][i] = new_return((i + 1) as u8)
+                let mut takeoff = waypoint.clone();
+                if ardupilot {
+                    // ArduPilot takes off from where it is and ignores the takeoff position
+                    takeoff.lat = 0.0;
+                    takeoff.lon = 0.0;
+                }
+                let mut item = new_takeoff(takeoff, options.item_altitude(takeoff_alt, None));
+                if vehicle_type == VehicleType::FixedWing {
+                    item["params"][0] = json!(FIXED_WING_TAKEOFF_PITCH);
+                }
+                items.push(item);
             } else {
-                plan["mission"]["items"][i] = new_waypoint(waypoints[i].clone(), (i + 1) as u8)
+                let mut location = waypoint.clone();
+                if ardupilot {
+                    // ArduPilot ignores the waypoint yaw, the heading is set with CONDITION_YAW
+                    location.heading = None;
+                }
+                let altitude = options.item_altitude(waypoint.alt, waypoint.terrain_alt);
+                if !waypoint.actions.is_empty() {
+                    // arrive without holding so the actions run before the hold
+                    location.hold_sec = 0;
+                }
+                if vehicle_type.flies_fixed_wing() && location.hold_sec > 0 {
+                    // a wing cannot hover, so holds are flown as a loiter around the waypoint
+                    items.push(new_loiter(location, altitude, options, jump_id));
+                } else {
+                    items.push(new_waypoint(location, altitude, jump_id));
+                }
+            }
+            jump_id += 1;
+            if i == 0 && vehicle_type == VehicleType::Vtol {
+                items.push(new_vtol_transition(VTOL_STATE_FW, jump_id));
+                jump_id += 1;
+            }
+            if let Some(heading) = waypoint.heading.filter(|_| ardupilot && i > 0) {
+                items.push(new_condition_yaw(heading, jump_id));
+                jump_id += 1;
+            }
+            if let Some(roi) = waypoint.roi {
+                items.push(new_roi_location(roi, jump_id));
+                jump_id += 1;
+            }
+            for action in &waypoint.actions {
+                items.push(new_payload_action(*action, jump_id));
+                jump_id += 1;
+            }
+            if i > 0 && waypoint.hold_sec > 0 && !waypoint.actions.is_empty() {
+                // hold in place at the waypoint the vehicle is already at
+                let mut location = waypoint.clone();
+                location.heading = location.heading.filter(|_| !ardupilot);
+                let altitude = options.item_altitude(waypoint.alt, waypoint.terrain_alt);
+                if vehicle_type.flies_fixed_wing() {
+                    items.push(new_loiter(location, altitude, options, jump_id));
+                } else {
+                    items.push(new_waypoint(location, altitude, jump_id));
+                }
+                jump_id += 1;
+            }
+        }
+        if options.repeat > 0 && waypoints.len() > 1 {
+            let from = options.repeat_from.clamp(1, waypoints.len() - 1);
+            items.push(new_do_jump(waypoint_ids[from], options.repeat, jump_id));
+            jump_id += 1;
+        }
+        // stop pointing at the region of interest before heading home
+        if waypoints.iter().any(|p| p.roi.is_some()) {
+            items.push(new_roi_none(jump_id));
+            jump_id += 1;
+        }
+        let return_alt = options.vehicle_return_alt(vehicle);
+        if let (true, Some(alt), Some(last), Some(launch)) = (
+            options.return_path,
+            return_alt,
+            waypoints.last(),
+            waypoints.first(),
+        ) {
+            // climb or descend to the return altitude in place, then fly home level at it
+            let (lat, lon) = match &options.landing {
+                Some(schedule) => schedule.pad(vehicle, launch),
+                None => (launch.lat, launch.lon),
+            };
+            for point in [
+                Point::new(last.lat, last.lon, alt, 0),
+                Point::new(lat, lon, alt, 0),
+            ] {
+                items.push(new_waypoint(
+                    point,
+                    options.item_altitude(alt, None),
+                    jump_id,
+                ));
+                jump_id += 1;
+            }
+        }
+        match (&options.landing, waypoints.first()) {
+            (Some(schedule), Some(launch)) => {
+                let (pad, delay) = schedule.slot(vehicle, launch, takeoff_alt);
+                let altitude = options.item_altitude(pad.alt, None);
+                if vehicle_type.flies_fixed_wing() {
+                    // wings are spaced by their approach altitude, they cannot hold in place
+                    items.extend(new_landing_pattern(&pad, altitude, options, &mut jump_id));
+                } else {
+                    items.extend(new_landing_sequence(pad, altitude, delay, &mut jump_id));
+                }
+            }
+            (None, Some(launch)) if vehicle_type.flies_fixed_wing() => {
+                items.extend(new_landing_pattern(
+                    launch,
+                    options.item_altitude(takeoff_alt, None),
+                    options,
+                    &mut jump_id,
+                ));
+            }
+            _ => items.push(new_return(jump_id)),
+        }
+        plan["mission"]["items"] = json!(items);
+        // waypoints without terrain data fall back to the profile's mode, leaving a mixed plan
+        let following = waypoints.iter().filter(|p| p.terrain_alt.is_some()).count();
+        if following > 0 && following + 1 == waypoints.len() {
+            plan["mission"]["globalPlanAltitudeMode"] = json!(ALTITUDE_MODE_CALC_ABOVE_TERRAIN);
+        } else if following > 0 {
+            plan["mission"]["globalPlanAltitudeMode"] = json!(ALTITUDE_MODE_MIXED);
+        } else if options.profile.altitude_mode == AltitudeMode::Amsl {
+            plan["mission"]["globalPlanAltitudeMode"] = json!(ALTITUDE_MODE_ABSOLUTE);
+        }
+        if let Some(alt) = return_alt {
+            plan["returnAltitude"] = json!(alt);
+        }
+        plan["geoFence"] = fence.to_qgc();
+        if let Some(points) = plan["rallyPoints"]["points"].as_array_mut() {
+            for point in rally {
+                points.push(json!([point.lat, point.lon, point.alt]));
             }
         }
 
@@ -265,15 +1400,15 @@ pub trait ShapeMission {
     }
 }
 
-fn new_takeoff(initial_position: Point) -> Value {
+fn new_takeoff(initial_position: Point, altitude: ItemAltitude) -> Value {
     json!({
-        "AMSLAltAboveTerrain": null,
-        "Altitude": 50,
-        "AltitudeMode": 1,
+        "AMSLAltAboveTerrain": altitude.amsl_above_terrain,
+        "Altitude": altitude.altitude,
+        "AltitudeMode": altitude.mode,
         "autoContinue": true,
         "command": 22,
                 "doJumpId": 1,
-                "frame": 3,
+                "frame": altitude.frame,
                 "params": [
                     0,
                     0,
@@ -281,38 +1416,297 @@ fn new_takeoff(initial_position: Point) -> Value {
                     null,
                     initial_position.lat,
                     initial_position.lon,
-                    initial_position.alt,
+                    altitude.param,
                 ],
                 "type": "SimpleItem"
     })
 }
 
-fn new_waypoint(location: Point, jump_id: u8) -> Value {
+fn new_waypoint(location: Point, altitude: ItemAltitude, jump_id: u16) -> Value {
     json!({
-        "AMSLAltAboveTerrain": null,
-        "Altitude": 50,
-        "AltitudeMode": 1,
+        "AMSLAltAboveTerrain": altitude.amsl_above_terrain,
+        "Altitude": altitude.altitude,
+        "AltitudeMode": altitude.mode,
         "autoContinue": true,
         "command": 16,
         "doJumpId": jump_id,
-        "frame": 3,
+        "frame": altitude.frame,
         "params": [
             location.hold_sec,
+            location.acceptance_radius.unwrap_or(0.0),
+            location.pass_radius.unwrap_or(0.0),
+            location.heading,
+            location.lat,
+            location.lon,
+            altitude.param,
+        ],
+        "type": "SimpleItem"
+    })
+}
+
+fn new_return(jump_id: u16) -> Value {
+    json!({
+        "autoContinue": true,
+        "command": 20,
+        "doJumpId": jump_id,
+        "frame": 2,
+        "params": [
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0
+        ],
+        "type": "SimpleItem"
+    })
+}
+
+fn new_payload_action(action: PayloadAction, jump_id: u16) -> Value {
+    let (command, params) = match action {
+        PayloadAction::Servo(servo, pwm) => (183, json!([servo, pwm, 0, 0, 0, 0, 0])),
+        PayloadAction::Relay(relay, on) => (181, json!([relay, on as u8, 0, 0, 0, 0, 0])),
+        PayloadAction::Gripper(gripper, release) => {
+            (211, json!([gripper, !release as u8, 0, 0, 0, 0, 0]))
+        }
+        PayloadAction::Digicam => (203, json!([0, 0, 0, 0, 1, 0, 0])),
+        PayloadAction::ImageCapture(interval, count) => {
+            (2000, json!([0, interval, count, 0, 0, 0, 0]))
+        }
+        PayloadAction::TriggerDistance(distance) => (206, json!([distance, 0, 1, 0, 0, 0, 0])),
+    };
+    json!({
+        "autoContinue": true,
+        "command": command,
+        "doJumpId": jump_id,
+        "frame": 2,
+        "params": params,
+        "type": "SimpleItem"
+    })
+}
+
+fn new_loiter(
+    location: Point,
+    altitude: ItemAltitude,
+    options: &PlanOptions,
+    jump_id: u16,
+) -> Value {
+    let (command, amount) = if options.loiter_turns {
+        // whole turns that take at least the hold time at cruise speed
+        let circumference = 2.0 * PI * options.turn_radius.max(1.0);
+        let turns = (location.hold_sec as f64 * options.profile.cruise_speed / circumference)
+            .ceil()
+            .max(1.0);
+        (18, turns)
+    } else {
+        (19, location.hold_sec as f64)
+    };
+    json!({
+        "AMSLAltAboveTerrain": altitude.amsl_above_terrain,
+        "Altitude": altitude.altitude,
+        "AltitudeMode": altitude.mode,
+        "autoContinue": true,
+        "command": command,
+        "doJumpId": jump_id,
+        "frame": altitude.frame,
+        "params": [
+            amount,
+            0,
+            options.turn_radius,
+            0,
+            location.lat,
+            location.lon,
+            altitude.param,
+        ],
+        "type": "SimpleItem"
+    })
+}
+
+fn new_vtol_transition(state: u8, jump_id: u16) -> Value {
+    json!({
+        "autoContinue": true,
+        "command": 3000,
+        "doJumpId": jump_id,
+        "frame": 2,
+        "params": [
+            state,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0
+        ],
+        "type": "SimpleItem"
+    })
+}
+
+// new_landing_pattern lands at the launch point, approaching on land_heading from a loiter
+// down to the approach altitude, VTOLs transition to multicopter before touching down
+fn new_landing_pattern(
+    launch: &Point,
+    approach: ItemAltitude,
+    options: &PlanOptions,
+    jump_id: &mut u16,
+) -> Vec<Value> {
+    let heading = options.land_heading * TO_RAD;
+    let distance = approach.height / (LANDING_GLIDE_SLOPE * TO_RAD).tan();
+    let (lat, lon) = from_local(
+        (launch.lat, launch.lon),
+        (-distance * heading.sin(), -distance * heading.cos()),
+    );
+
+    let mut items = vec![json!({
+        "autoContinue": true,
+        "command": 189,
+        "doJumpId": *jump_id,
+        "frame": 2,
+        "params": [0, 0, 0, 0, 0, 0, 0],
+        "type": "SimpleItem"
+    })];
+    *jump_id += 1;
+    items.push(json!({
+        "AMSLAltAboveTerrain": approach.amsl_above_terrain,
+        "Altitude": approach.altitude,
+        "AltitudeMode": approach.mode,
+        "autoContinue": true,
+        "command": 31,
+        "doJumpId": *jump_id,
+        "frame": approach.frame,
+        "params": [1, options.turn_radius, 0, 0, lat, lon, approach.param],
+        "type": "SimpleItem"
+    }));
+    *jump_id += 1;
+    if options.profile.vehicle_type == VehicleType::Vtol {
+        items.push(new_vtol_transition(VTOL_STATE_MC, *jump_id));
+        *jump_id += 1;
+    }
+    items.push(new_land(launch, &approach, *jump_id));
+    *jump_id += 1;
+    items
+}
+
+// new_landing_sequence flies over the pad at the holding altitude, holds for delay seconds
+// and lands straight down
+fn new_landing_sequence(
+    pad: Point,
+    altitude: ItemAltitude,
+    delay: u32,
+    jump_id: &mut u16,
+) -> Vec<Value> {
+    let hold = json!({
+        "AMSLAltAboveTerrain": altitude.amsl_above_terrain,
+        "Altitude": altitude.altitude,
+        "AltitudeMode": altitude.mode,
+        "autoContinue": true,
+        "command": 19,
+        "doJumpId": *jump_id + 1,
+        "frame": altitude.frame,
+        "params": [delay, 0, 0, 0, pad.lat, pad.lon, altitude.param],
+        "type": "SimpleItem"
+    });
+    let land_id = if delay > 0 {
+        *jump_id + 2
+    } else {
+        *jump_id + 1
+    };
+    let land = new_land(&pad, &altitude, land_id);
+
+    let mut items = vec![new_waypoint(pad, altitude, *jump_id)];
+    if delay > 0 {
+        items.push(hold);
+    }
+    items.push(land);
+    *jump_id = land_id + 1;
+    items
+}
+
+// new_land touches down at location, on the ground below the item flown at altitude
+fn new_land(location: &Point, altitude: &ItemAltitude, jump_id: u16) -> Value {
+    json!({
+        "AMSLAltAboveTerrain": null,
+        "Altitude": 0,
+        "AltitudeMode": altitude.mode,
+        "autoContinue": true,
+        "command": 21,
+        "doJumpId": jump_id,
+        "frame": altitude.frame,
+        "params": [
+            0,
             0,
             0,
             null,
             location.lat,
             location.lon,
-            location.alt,
+            altitude.param - altitude.height
         ],
         "type": "SimpleItem"
     })
 }
 
-fn new_return(jump_id: u8) -> Value {
+// new_do_jump jumps back to the item with doJumpId target, repeat more times
+fn new_do_jump(target: u16, repeat: u16, jump_id: u16) -> Value {
     json!({
         "autoContinue": true,
-        "command": 20,
+        "command": 177,
+        "doJumpId": jump_id,
+        "frame": 2,
+        "params": [
+            target,
+            repeat,
+            0,
+            0,
+            0,
+            0,
+            0
+        ],
+        "type": "SimpleItem"
+    })
+}
+
+fn new_condition_yaw(heading: f64, jump_id: u16) -> Value {
+    json!({
+        "autoContinue": true,
+        "command": 115,
+        "doJumpId": jump_id,
+        "frame": 2,
+        "params": [
+            heading,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0
+        ],
+        "type": "SimpleItem"
+    })
+}
+
+fn new_roi_location(roi: (f64, f64, u8), jump_id: u16) -> Value {
+    json!({
+        "autoContinue": true,
+        "command": 195,
+        "doJumpId": jump_id,
+        "frame": 3,
+        "params": [
+            0,
+            0,
+            0,
+            0,
+            roi.0,
+            roi.1,
+            roi.2
+        ],
+        "type": "SimpleItem"
+    })
+}
+
+fn new_roi_none(jump_id: u16) -> Value {
+    json!({
+        "autoContinue": true,
+        "command": 197,
         "doJumpId": jump_id,
         "frame": 2,
         "params": [
@@ -328,7 +1722,55 @@ fn new_return(jump_id: u8) -> Value {
     })
 }
 
-fn new_qgc_plan() -> Value {
+// new_simple_item creates an item from its raw command, frame and seven params, for items read
+// back from a vehicle
+pub fn new_simple_item(
+    command: u32,
+    frame: u32,
+    params: [f64; 7],
+    auto_continue: bool,
+    jump_id: u16,
+) -> Value {
+    json!({
+        "autoContinue": auto_continue,
+        "command": command,
+        "doJumpId": jump_id,
+        "frame": frame,
+        "params": params,
+        "type": "SimpleItem"
+    })
+}
+
+// new_pulled_plan creates a plan from what was pulled from a vehicle, keeping its
+// MAV_AUTOPILOT and MAV_TYPE, home is (lat, lon, amsl)
+pub fn new_pulled_plan(
+    firmware_type: i32,
+    vehicle_type: i32,
+    home: (f64, f64, f64),
+    items: Vec<Value>,
+    fence: &Fence,
+    rally: &[(f64, f64, f64)],
+) -> Value {
+    let mut plan = new_qgc_plan(&PlanProfile::default(), None, home.2);
+    plan["mission"]["firmwareType"] = json!(firmware_type);
+    plan["mission"]["vehicleType"] = json!(vehicle_type);
+    plan["mission"]["plannedHomePosition"] = json!([home.0, home.1, home.2]);
+    plan["mission"]["items"] = json!(items);
+    plan["geoFence"] = fence.to_qgc();
+    if let Some(points) = plan["rallyPoints"]["points"].as_array_mut() {
+        for (lat, lon, alt) in rally {
+            points.push(json!([lat, lon, alt]));
+        }
+    }
+    plan
+}
+
+// new_qgc_plan creates an empty plan, home is planned at the launch point when known
+fn new_qgc_plan(profile: &PlanProfile, launch: Option<&Point>, home_elevation: f64) -> Value {
+    let home = match launch {
+        Some(launch) => json!([launch.lat, launch.lon, home_elevation]),
+        None => json!([0, 0, home_elevation]),
+    };
     json!({
         "fileType": "Plan",
         "geoFence": {
@@ -338,17 +1780,13 @@ fn new_qgc_plan() -> Value {
         },
         "groundStation": "QGroundControl",
         "mission": {
-            "cruiseSpeed": 15,
-            "firmwareType": 12,
-            "globalPlanAltitudeMode": 1,
-            "hoverSpeed": 5,
+            "cruiseSpeed": profile.cruise_speed,
+            "firmwareType": profile.firmware.mav_autopilot(),
+            "globalPlanAltitudeMode": ALTITUDE_MODE_RELATIVE,
+            "hoverSpeed": profile.hover_speed,
             "items": [],
-            "plannedHomePosition": [
-                0,
-                0,
-                50
-            ],
-            "vehicleType": 2,
+            "plannedHomePosition": home,
+            "vehicleType": profile.vehicle_type.mav_type(),
             "version": 2
         },
         "rallyPoints": {
@@ -0,0 +1,318 @@
+use serde_json::{json, Value};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// 1 degree lat = 111111 m
+const METERS_PER_DEGREE: f64 = 111111_f64;
+// number of samples used to approximate the buffer circle around each hull vertex
+const BUFFER_SEGMENTS: usize = 16;
+
+// FencePolygon is a closed polygon of (lat, lon) vertices
+#[derive(Clone, Debug)]
+pub struct FencePolygon {
+    pub inclusion: bool,
+    pub vertices: Vec<(f64, f64)>,
+}
+
+// FenceCircle is a circle centered at (lat, lon) with a radius in meters
+#[derive(Clone, Debug)]
+pub struct FenceCircle {
+    pub inclusion: bool,
+    pub center: (f64, f64),
+    pub radius: f64,
+}
+
+// Fence holds the inclusion and exclusion zones of a QGC geoFence section
+#[derive(Clone, Debug, Default)]
+pub struct Fence {
+    pub polygons: Vec<FencePolygon>,
+    pub circles: Vec<FenceCircle>,
+}
+
+impl Fence {
+    // inclusion_hull creates a fence with a single inclusion polygon covering every point
+    // buffered outward by margin meters
+    pub fn inclusion_hull(points: &[(f64, f64)], margin: f64) -> Fence {
+        let mut fence = Fence::default();
+        if points.is_empty() {
+            return fence;
+        }
+        fence.polygons.push(FencePolygon {
+            inclusion: true,
+            vertices: buffered_hull(points, margin),
+        });
+        fence
+    }
+
+    // load reads the geoFence section of a QGC plan file, or a file holding only that section
+    pub fn load(path: &Path) -> io::Result<Fence> {
+        let data = fs::read_to_string(path)?;
+        let value: Value = serde_json::from_str(data.as_str())?;
+        let section = if value["geoFence"].is_object() {
+            &value["geoFence"]
+        } else {
+            &value
+        };
+        Fence::from_qgc(section).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid geofence in {}", path.display()),
+            )
+        })
+    }
+
+    // from_qgc parses a QGC geoFence json section
+    pub fn from_qgc(value: &Value) -> Option<Fence> {
+        let mut fence = Fence::default();
+        for polygon in value["polygons"].as_array()? {
+            let mut vertices = vec![];
+            for vertex in polygon["polygon"].as_array()? {
+                vertices.push((vertex[0].as_f64()?, vertex[1].as_f64()?));
+            }
+            fence.polygons.push(FencePolygon {
+                inclusion: polygon["inclusion"].as_bool().unwrap_or(true),
+                vertices,
+            });
+        }
+        for circle in value["circles"].as_array()? {
+            let center = &circle["circle"]["center"];
+            fence.circles.push(FenceCircle {
+                inclusion: circle["inclusion"].as_bool().unwrap_or(true),
+                center: (center[0].as_f64()?, center[1].as_f64()?),
+                radius: circle["circle"]["radius"].as_f64()?,
+            });
+        }
+        Some(fence)
+    }
+
+    // set_inclusion overrides the inclusion flag on every zone, used for exclusion zone files
+    pub fn set_inclusion(&mut self, inclusion: bool) {
+        for polygon in self.polygons.iter_mut() {
+            polygon.inclusion = inclusion;
+        }
+        for circle in self.circles.iter_mut() {
+            circle.inclusion = inclusion;
+        }
+    }
+
+    pub fn extend(&mut self, other: &Fence) {
+        self.polygons.extend(other.polygons.iter().cloned());
+        self.circles.extend(other.circles.iter().cloned());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.polygons.is_empty() && self.circles.is_empty()
+    }
+
+    // contains checks a position is inside an inclusion zone, when any exist, and outside
+    // every exclusion zone
+    pub fn contains(&self, lat: f64, lon: f64) -> bool {
+        let mut has_inclusion = false;
+        let mut included = false;
+        for polygon in &self.polygons {
+            let inside = polygon_contains(&polygon.vertices, (lat, lon));
+            if polygon.inclusion {
+                has_inclusion = true;
+                included |= inside;
+            } else if inside {
+                return false;
+            }
+        }
+        for circle in &self.circles {
+            let (x, y) = to_local(circle.center, (lat, lon));
+            let inside = x.hypot(y) <= circle.radius;
+            if circle.inclusion {
+                has_inclusion = true;
+                included |= inside;
+            } else if inside {
+                return false;
+            }
+        }
+        !has_inclusion || included
+    }
+
+    // to_polygons approximates every circle as a polygon, for autopilots that only accept
+    // polygon fences, inclusion circles are inscribed and exclusion circles circumscribed so
+    // the polygon never allows more than the circle
+    pub fn to_polygons(&self) -> Vec<FencePolygon> {
+        let mut polygons = self.polygons.clone();
+        for circle in &self.circles {
+            let radius = if circle.inclusion {
+                circle.radius
+            } else {
+                circle.radius / (std::f64::consts::PI / BUFFER_SEGMENTS as f64).cos()
+            };
+            let vertices = (0..BUFFER_SEGMENTS)
+                .map(|i| {
+                    let rad = 2.0 * std::f64::consts::PI * (i as f64) / (BUFFER_SEGMENTS as f64);
+                    from_local(circle.center, (radius * rad.cos(), radius * rad.sin()))
+                })
+                .collect();
+            polygons.push(FencePolygon {
+                inclusion: circle.inclusion,
+                vertices,
+            });
+        }
+        polygons
+    }
+
+    // to_qgc serializes the fence into a QGC geoFence json section
+    pub fn to_qgc(&self) -> Value {
+        let polygons: Vec<Value> = self
+            .polygons
+            .iter()
+            .map(|p| {
+                let vertices: Vec<Value> = p
+                    .vertices
+                    .iter()
+                    .map(|(lat, lon)| json!([lat, lon]))
+                    .collect();
+                json!({
+                    "inclusion": p.inclusion,
+                    "polygon": vertices,
+                    "version": 1
+                })
+            })
+            .collect();
+        let circles: Vec<Value> = self
+            .circles
+            .iter()
+            .map(|c| {
+                json!({
+                    "circle": {
+                        "center": [c.center.0, c.center.1],
+                        "radius": c.radius
+                    },
+                    "inclusion": c.inclusion,
+                    "version": 1
+                })
+            })
+            .collect();
+        json!({
+            "circles": circles,
+            "polygons": polygons,
+            "version": 2
+        })
+    }
+}
+
+// buffered_hull returns the convex hull of points grown outward by margin meters
+fn buffered_hull(points: &[(f64, f64)], margin: f64) -> Vec<(f64, f64)> {
+    let origin = points[0];
+    let mut local = vec![];
+    for point in points {
+        let (x, y) = to_local(origin, *point);
+        if margin > 0.0 {
+            for i in 0..BUFFER_SEGMENTS {
+                let rad = 2.0 * std::f64::consts::PI * (i as f64) / (BUFFER_SEGMENTS as f64);
+                local.push((x + margin * rad.cos(), y + margin * rad.sin()));
+            }
+        } else {
+            local.push((x, y));
+        }
+    }
+    convex_hull(local)
+        .into_iter()
+        .map(|p| from_local(origin, p))
+        .collect()
+}
+
+// convex_hull uses the monotone chain algorithm, returning vertices counter clockwise
+fn convex_hull(mut points: Vec<(f64, f64)>) -> Vec<(f64, f64)> {
+    points.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.total_cmp(&b.1)));
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+    let cross = |o: (f64, f64), a: (f64, f64), b: (f64, f64)| {
+        (a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0)
+    };
+    let half_hull = |ordered: &mut dyn Iterator<Item = &(f64, f64)>| {
+        let mut half: Vec<(f64, f64)> = vec![];
+        for p in ordered {
+            while half.len() >= 2 && cross(half[half.len() - 2], half[half.len() - 1], *p) <= 0.0 {
+                half.pop();
+            }
+            half.push(*p);
+        }
+        // last point is the first point of the other half
+        half.pop();
+        half
+    };
+    let mut hull = half_hull(&mut points.iter());
+    hull.extend(half_hull(&mut points.iter().rev()));
+    hull
+}
+
+// polygon_contains uses ray casting to check if point is inside the polygon vertices
+pub fn polygon_contains(vertices: &[(f64, f64)], point: (f64, f64)) -> bool {
+    let mut inside = false;
+    let mut j = vertices.len().wrapping_sub(1);
+    for i in 0..vertices.len() {
+        let (lat_i, lon_i) = vertices[i];
+        let (lat_j, lon_j) = vertices[j];
+        if (lat_i > point.0) != (lat_j > point.0)
+            && point.1 < (lon_j - lon_i) * (point.0 - lat_i) / (lat_j - lat_i) + lon_i
+        {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+// to_local projects (lat, lon) to east/north meters relative to origin
+pub fn to_local(origin: (f64, f64), point: (f64, f64)) -> (f64, f64) {
+    let x = (point.1 - origin.1) * METERS_PER_DEGREE * origin.0.to_radians().cos();
+    let y = (point.0 - origin.0) * METERS_PER_DEGREE;
+    (x, y)
+}
+
+// from_local converts east/north meters relative to origin back to (lat, lon)
+pub fn from_local(origin: (f64, f64), point: (f64, f64)) -> (f64, f64) {
+    let lat = origin.0 + point.1 / METERS_PER_DEGREE;
+    let lon = origin.1 + point.0 / (METERS_PER_DEGREE * origin.0.to_radians().cos());
+    (lat, lon)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inclusion_hull_covers_every_point_with_margin() {
+        let points = [(47.0, 8.0), (47.001, 8.0), (47.0, 8.001), (47.0005, 8.0005)];
+        let fence = Fence::inclusion_hull(&points, 10.0);
+        for (lat, lon) in points {
+            assert!(fence.contains(lat, lon));
+        }
+        // 5 m outside the first point is inside the 10 m margin, 20 m is not
+        assert!(fence.contains(47.0 - 5.0 / METERS_PER_DEGREE, 8.0));
+        assert!(!fence.contains(47.0 - 20.0 / METERS_PER_DEGREE, 8.0));
+    }
+
+    #[test]
+    fn convex_hull_drops_inner_points() {
+        let square = vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0), (0.5, 0.5)];
+        let hull = convex_hull(square);
+        assert_eq!(hull.len(), 4);
+        assert!(!hull.contains(&(0.5, 0.5)));
+    }
+
+    #[test]
+    fn contains_excludes_exclusion_zones() {
+        let mut fence = Fence::inclusion_hull(&[(47.0, 8.0), (47.01, 8.0), (47.0, 8.01)], 0.0);
+        fence.circles.push(FenceCircle {
+            inclusion: false,
+            center: (47.002, 8.002),
+            radius: 50.0,
+        });
+        assert!(fence.contains(47.004, 8.001));
+        assert!(!fence.contains(47.002, 8.002));
+        assert!(!fence.contains(47.01, 8.01));
+        // without inclusion zones only the exclusions apply
+        fence.polygons.clear();
+        assert!(fence.contains(47.01, 8.01));
+    }
+}
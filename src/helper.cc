@@ -8,11 +8,352 @@
 #include <string>
 #include <ctime>
 #include <fstream>
+#include <atomic>
+#include <cmath>
+#include <mutex>
+#include <algorithm>
+#include <condition_variable>
 #include "include/helper.h"
 
 using std::this_thread::sleep_for;
 using std::chrono::seconds;
 
+// MAV_AUTOPILOT and MAV_TYPE values passed from the plan's firmwareType and vehicleType
+const int32_t AUTOPILOT_ARDUPILOT = 3;
+const int32_t TYPE_FIXED_WING = 1;
+const int32_t TYPE_VTOL_FIRST = 19;
+const int32_t TYPE_VTOL_LAST = 22;
+
+// ArduPilot custom modes
+const uint32_t COPTER_MODE_AUTO = 3;
+const uint32_t COPTER_MODE_GUIDED = 4;
+const uint32_t PLANE_MODE_AUTO = 10;
+const uint32_t PLANE_MODE_GUIDED = 15;
+
+// run_qgc_plan return codes for the stage that failed, matched by the rust Failure
+const int32_t RUN_FAILED = -1;
+const int32_t RUN_UPLOAD_FAILED = -2;
+const int32_t RUN_ARM_FAILED = -3;
+// the mission downloaded after the upload differs from the plan
+const int32_t RUN_VERIFY_FAILED = -5;
+// released from the start barrier without starting, not a failure
+const int32_t RUN_NOT_STARTED = -4;
+
+// seconds a vehicle gets to pass its health checks before its run fails
+const int HEALTH_TIMEOUT_SEC = 120;
+
+// failures reported through rust_cb_run_failure once the mission is running
+const int32_t FAILURE_LINK_LOST = 1;
+const int32_t FAILURE_FAILSAFE = 2;
+
+// tolerances when comparing the downloaded mission with the plan, params are relative to
+// their value, positions are in the scaled x and y of the item frame and altitudes in meters
+const float PARAM_TOLERANCE = 1e-4;
+const int32_t POSITION_TOLERANCE = 1;
+const float ALTITUDE_TOLERANCE = 0.01;
+
+// telemetry streams, passed with each report as the stream that changed
+const int32_t TELEMETRY_BATTERY = 0;
+const int32_t TELEMETRY_FLIGHT_MODE = 1;
+const int32_t TELEMETRY_ARMED = 2;
+const int32_t TELEMETRY_IN_AIR = 3;
+const int32_t TELEMETRY_GPS_INFO = 4;
+const int32_t TELEMETRY_HEADING = 5;
+const int32_t TELEMETRY_VELOCITY = 6;
+const int32_t TELEMETRY_RC_STATUS = 7;
+const int32_t TELEMETRY_STATUS_TEXT = 8;
+
+// MAV_MISSION_TYPE of the items pulled from a vehicle
+const uint8_t MISSION_TYPE_MISSION = 0;
+const uint8_t MISSION_TYPE_FENCE = 1;
+const uint8_t MISSION_TYPE_RALLY = 2;
+// attempts and timeout for each message of a fence or rally download
+const int DOWNLOAD_RETRIES = 3;
+const auto DOWNLOAD_TIMEOUT = std::chrono::milliseconds(1500);
+
+// MAVLink commands without a MAVSDK wrapper
+const uint16_t CMD_DO_SET_MODE = 176;
+const uint16_t CMD_MISSION_START = 300;
+const uint16_t CMD_DO_JUMP = 177;
+const float MODE_FLAG_CUSTOM_MODE_ENABLED = 1;
+
+// ardupilot helpers
+static bool send_command(mavsdk::MavlinkPassthrough &passthrough,
+                         uint16_t command,
+                         float param1,
+                         float param2) {
+  mavsdk::MavlinkPassthrough::CommandLong cmd{};
+  cmd.target_sysid = passthrough.get_target_sysid();
+  cmd.target_compid = passthrough.get_target_compid();
+  cmd.command = command;
+  cmd.param1 = param1;
+  cmd.param2 = param2;
+  return passthrough.send_command_long(cmd) == mavsdk::MavlinkPassthrough::Result::Success;
+}
+
+static bool set_ardupilot_mode(mavsdk::MavlinkPassthrough &passthrough, uint32_t custom_mode) {
+  return send_command(passthrough,
+                      CMD_DO_SET_MODE,
+                      MODE_FLAG_CUSTOM_MODE_ENABLED,
+                      static_cast<float>(custom_mode));
+}
+
+// ArduPilot always treats item 0 as home, so the plan items have to start from item 1 and
+// DO_JUMP targets, the index of the target item, move up by one
+static void insert_ardupilot_home(std::vector<mavsdk::MissionRaw::MissionItem> &items) {
+  mavsdk::MissionRaw::MissionItem home = items.front();
+  home.command = 16;
+  home.frame = 0;
+  home.current = 0;
+  home.param1 = home.param2 = home.param3 = home.param4 = 0;
+  home.z = 0;
+  items.insert(items.begin(), home);
+  for (uint32_t i = 0; i < items.size(); i++) {
+    items[i].seq = i;
+    if (items[i].command == CMD_DO_JUMP) {
+      items[i].param1 += 1;
+    }
+  }
+}
+
+// item_difference describes how the item held by the vehicle differs from the item sent,
+// empty when they match within tolerance
+static std::string item_difference(mavsdk::MissionRaw::MissionItem const &sent,
+                                   mavsdk::MissionRaw::MissionItem const &held) {
+  if (sent.command != held.command) {
+    return string_format("command %u, vehicle has %u", sent.command, held.command);
+  }
+  if (sent.frame != held.frame) {
+    return string_format("frame %u, vehicle has %u", sent.frame, held.frame);
+  }
+  const float sent_params[] = {sent.param1, sent.param2, sent.param3, sent.param4};
+  const float held_params[] = {held.param1, held.param2, held.param3, held.param4};
+  for (int i = 0; i < 4; i++) {
+    const float a = sent_params[i];
+    const float b = held_params[i];
+    if (std::isnan(a) && std::isnan(b)) {
+      continue;
+    }
+    if (std::isnan(a) || std::isnan(b)
+        || std::fabs(a - b) > PARAM_TOLERANCE * std::fmax(1.0f, std::fabs(a))) {
+      return string_format("param%d %g, vehicle has %g", i + 1, a, b);
+    }
+  }
+  if (std::abs(sent.x - held.x) > POSITION_TOLERANCE
+      || std::abs(sent.y - held.y) > POSITION_TOLERANCE) {
+    return string_format("position %d, %d, vehicle has %d, %d", sent.x, sent.y, held.x, held.y);
+  }
+  if (std::fabs(sent.z - held.z) > ALTITUDE_TOLERANCE) {
+    return string_format("altitude %g, vehicle has %g", sent.z, held.z);
+  }
+  if (sent.autocontinue != held.autocontinue) {
+    return string_format("autocontinue %u, vehicle has %u", sent.autocontinue, held.autocontinue);
+  }
+  return "";
+}
+
+// verify_mission reports every item the vehicle holds that differs from the items sent, items
+// count from the first plan item as ArduPilot replaces its item 0 with its own home
+static bool verify_mission(std::vector<mavsdk::MissionRaw::MissionItem> const &sent,
+                           std::vector<mavsdk::MissionRaw::MissionItem> const &held,
+                           bool ardupilot,
+                           void *rust_cb,
+                           rust_cb_run_mismatch cb_mismatch) {
+  bool match = true;
+  if (sent.size() != held.size()) {
+    const auto reason = string_format("%zu items sent, vehicle has %zu", sent.size(), held.size());
+    cb_mismatch(rust_cb, -1, reason.c_str());
+    match = false;
+  }
+  const size_t first = ardupilot ? 1 : 0;
+  for (size_t i = first; i < std::min(sent.size(), held.size()); i++) {
+    const auto reason = item_difference(sent[i], held[i]);
+    if (!reason.empty()) {
+      cb_mismatch(rust_cb, static_cast<int32_t>(i - first), reason.c_str());
+      match = false;
+    }
+  }
+  return match;
+}
+
+// ArduPilot does not report sensor calibration through health, its prearm checks cover it and
+// decide whether it is armable
+static HealthReport read_health(mavsdk::Telemetry &telemetry, bool ardupilot) {
+  const auto health = telemetry.health();
+  const auto gps_info = telemetry.gps_info();
+  HealthReport report{};
+  report.gps_fix_type = static_cast<int32_t>(gps_info.fix_type);
+  report.satellites = gps_info.num_satellites;
+  report.home_ok = health.is_home_position_ok;
+  report.gyro_ok = ardupilot ? health.is_armable : health.is_gyrometer_calibration_ok;
+  report.accel_ok = ardupilot ? health.is_armable : health.is_accelerometer_calibration_ok;
+  report.mag_ok = ardupilot ? health.is_armable : health.is_magnetometer_calibration_ok;
+  report.battery_percent = telemetry.battery().remaining_percent * 100.0f;
+  report.armable = health.is_armable;
+  return report;
+}
+
+// download_items downloads the fence or rally items over the MAVLink mission protocol, MAVSDK's
+// MissionRaw only downloads the mission itself
+static bool download_items(mavsdk::MavlinkPassthrough &passthrough,
+                           uint8_t mission_type,
+                           std::vector<mavlink_mission_item_int_t> &items) {
+  std::mutex mutex;
+  std::condition_variable received;
+  int32_t count = -1;
+  items.clear();
+
+  passthrough.subscribe_message_async(MAVLINK_MSG_ID_MISSION_COUNT,
+                                      [&](const mavlink_message_t &message) {
+    mavlink_mission_count_t mission_count;
+    mavlink_msg_mission_count_decode(&message, &mission_count);
+    if (mission_count.mission_type == mission_type) {
+      std::lock_guard<std::mutex> lock(mutex);
+      count = mission_count.count;
+      received.notify_all();
+    }
+  });
+  passthrough.subscribe_message_async(MAVLINK_MSG_ID_MISSION_ITEM_INT,
+                                      [&](const mavlink_message_t &message) {
+    mavlink_mission_item_int_t item;
+    mavlink_msg_mission_item_int_decode(&message, &item);
+    std::lock_guard<std::mutex> lock(mutex);
+    if (item.mission_type == mission_type && item.seq == items.size()) {
+      items.push_back(item);
+      received.notify_all();
+    }
+  });
+
+  // each request is sent again until it is answered or out of retries
+  auto request = [&](std::function<void(mavlink_message_t &)> pack,
+                     std::function<bool()> answered) {
+    for (int attempt = 0; attempt < DOWNLOAD_RETRIES; attempt++) {
+      mavlink_message_t message;
+      pack(message);
+      passthrough.send_message(message);
+      std::unique_lock<std::mutex> lock(mutex);
+      if (received.wait_for(lock, DOWNLOAD_TIMEOUT, answered)) {
+        return true;
+      }
+    }
+    return false;
+  };
+
+  const uint8_t sysid = passthrough.get_our_sysid();
+  const uint8_t compid = passthrough.get_our_compid();
+  const uint8_t target_sysid = passthrough.get_target_sysid();
+  const uint8_t target_compid = passthrough.get_target_compid();
+  bool complete = request([&](mavlink_message_t &message) {
+    mavlink_msg_mission_request_list_pack(
+        sysid, compid, &message, target_sysid, target_compid, mission_type);
+  }, [&]() { return count >= 0; });
+  for (int32_t seq = 0; complete && seq < count; seq++) {
+    complete = request([&](mavlink_message_t &message) {
+      mavlink_msg_mission_request_int_pack(
+          sysid, compid, &message, target_sysid, target_compid, seq, mission_type);
+    }, [&]() { return static_cast<int32_t>(items.size()) > seq; });
+  }
+
+  passthrough.subscribe_message_async(MAVLINK_MSG_ID_MISSION_COUNT, nullptr);
+  passthrough.subscribe_message_async(MAVLINK_MSG_ID_MISSION_ITEM_INT, nullptr);
+  if (complete) {
+    mavlink_message_t ack;
+    mavlink_msg_mission_ack_pack(
+        sysid, compid, &ack, target_sysid, target_compid, MAV_MISSION_ACCEPTED, mission_type);
+    passthrough.send_message(ack);
+  }
+  return complete;
+}
+
+// TelemetryStream gathers the telemetry subscriptions into one report, every update sends the
+// whole report along with the stream that changed
+struct TelemetryStream {
+  std::mutex mutex;
+  TelemetryReport report{};
+  void *rust_cb;
+  rust_cb_run_telemetry cb;
+
+  TelemetryStream(void *rust_cb, rust_cb_run_telemetry cb) : rust_cb(rust_cb), cb(cb) {
+    report.battery_percent = NAN;
+  }
+
+  template<typename F>
+  void update(int32_t stream, F apply, char const *text = "") {
+    std::lock_guard<std::mutex> lock(mutex);
+    apply(report);
+    cb(rust_cb, stream, &report, text);
+  }
+
+  void status_text(mavsdk::Telemetry::StatusText const &status_text) {
+    update(TELEMETRY_STATUS_TEXT, [](TelemetryReport &) {}, status_text.text.c_str());
+  }
+};
+
+// subscribe_telemetry sets the stream rates and forwards every stream, a rate the vehicle
+// refuses is logged and the stream left at its default rate
+static void subscribe_telemetry(mavsdk::Telemetry &telemetry,
+                                TelemetryRates const *rates,
+                                TelemetryStream &stream,
+                                rust_cb_log cb_log) {
+  const std::pair<mavsdk::Telemetry::Result, char const *> rate_results[] = {
+      {telemetry.set_rate_battery(rates->battery), "battery"},
+      {telemetry.set_rate_gps_info(rates->gps_info), "GPS info"},
+      {telemetry.set_rate_in_air(rates->in_air), "in air"},
+      {telemetry.set_rate_velocity_ned(rates->velocity), "velocity"},
+      {telemetry.set_rate_rc_status(rates->rc_status), "RC status"},
+  };
+  for (const auto &rate_result : rate_results) {
+    if (rate_result.first != mavsdk::Telemetry::Result::Success) {
+      cb_log(stream.rust_cb, string_format("Failed to set %s rate", rate_result.second).c_str());
+    }
+  }
+
+  telemetry.subscribe_battery([&stream](mavsdk::Telemetry::Battery battery) {
+    stream.update(TELEMETRY_BATTERY, [&](TelemetryReport &report) {
+      report.battery_percent = battery.remaining_percent * 100.0f;
+      report.battery_voltage = battery.voltage_v;
+    });
+  });
+  telemetry.subscribe_flight_mode([&stream](mavsdk::Telemetry::FlightMode flight_mode) {
+    stream.update(TELEMETRY_FLIGHT_MODE, [&](TelemetryReport &report) {
+      report.flight_mode = static_cast<int32_t>(flight_mode);
+    });
+  });
+  telemetry.subscribe_armed([&stream](bool armed) {
+    stream.update(TELEMETRY_ARMED, [&](TelemetryReport &report) { report.armed = armed; });
+  });
+  telemetry.subscribe_in_air([&stream](bool in_air) {
+    stream.update(TELEMETRY_IN_AIR, [&](TelemetryReport &report) { report.in_air = in_air; });
+  });
+  telemetry.subscribe_gps_info([&stream](mavsdk::Telemetry::GpsInfo gps_info) {
+    stream.update(TELEMETRY_GPS_INFO, [&](TelemetryReport &report) {
+      report.gps_fix_type = static_cast<int32_t>(gps_info.fix_type);
+      report.satellites = gps_info.num_satellites;
+    });
+  });
+  telemetry.subscribe_heading([&stream](mavsdk::Telemetry::Heading heading) {
+    stream.update(TELEMETRY_HEADING, [&](TelemetryReport &report) {
+      report.heading_deg = static_cast<float_t>(heading.heading_deg);
+    });
+  });
+  telemetry.subscribe_velocity_ned([&stream](mavsdk::Telemetry::VelocityNed velocity) {
+    stream.update(TELEMETRY_VELOCITY, [&](TelemetryReport &report) {
+      report.velocity_north = velocity.north_m_s;
+      report.velocity_east = velocity.east_m_s;
+      report.velocity_down = velocity.down_m_s;
+    });
+  });
+  telemetry.subscribe_rc_status([&stream](mavsdk::Telemetry::RcStatus rc_status) {
+    stream.update(TELEMETRY_RC_STATUS, [&](TelemetryReport &report) {
+      report.rc_available = rc_status.is_available;
+      report.rc_signal_percent = rc_status.signal_strength_percent;
+    });
+  });
+  telemetry.subscribe_status_text([&stream](mavsdk::Telemetry::StatusText status_text) {
+    stream.status_text(status_text);
+  });
+}
+
 // shim code
 extern "C" {
 SDKHandle new_mavsdk() {
@@ -25,7 +366,7 @@ void del_mavsdk(SDKHandle p) {
 
 // helper code
 extern "C" {
-SHandle connect(SDKHandle sdk, char const *addr) {
+SHandle connect(SDKHandle sdk, char const *addr, int32_t timeout) {
   mavsdk::ConnectionResult connection_result = sdk->add_any_connection(std::string(addr));
   if (connection_result != mavsdk::ConnectionResult::Success) {
     std::cerr << "Connection failed: " << connection_result << '\n';
@@ -46,7 +387,7 @@ SHandle connect(SDKHandle sdk, char const *addr) {
     }
   });
 
-  if (fut.wait_for(std::chrono::seconds(3)) == std::future_status::timeout) {
+  if (fut.wait_for(std::chrono::seconds(timeout)) == std::future_status::timeout) {
     std::cerr << "No autopilot found.\n";
     return nullptr;
   }
@@ -55,71 +396,456 @@ SHandle connect(SDKHandle sdk, char const *addr) {
 }
 
 int32_t run_qgc_plan(SHandle system,
-                     char const *path,
+                     MRHandle items,
                      void *rust_cb,
                      rust_cb_run_position cb_pos,
                      rust_cb_run_progress cb_pro,
                      rust_cb_run_complete cb_com,
-                     rust_cb_log cb_log) {
+                     rust_cb_log cb_log,
+                     rust_cb_run_ready cb_ready,
+                     rust_cb_run_failure cb_fail,
+                     rust_cb_run_health cb_health,
+                     rust_cb_run_telemetry cb_telemetry,
+                     rust_cb_run_mismatch cb_mismatch,
+                     TelemetryRates const *rates,
+                     int32_t firmware,
+                     int32_t vehicle_type) {
+  // declared before the plugins so it outlives their subscriptions
+  TelemetryStream stream{rust_cb, cb_telemetry};
+
   // setup plugins
   auto telemetry = mavsdk::Telemetry{*system};
   auto action = mavsdk::Action{*system};
   auto mission_raw = mavsdk::MissionRaw(*system);
+  auto passthrough = mavsdk::MavlinkPassthrough{*system};
 
-  const mavsdk::Telemetry::Result set_rate_result = telemetry.set_rate_position(1.0);
+  const bool ardupilot = firmware == AUTOPILOT_ARDUPILOT;
+  const bool plane = vehicle_type == TYPE_FIXED_WING
+      || (vehicle_type >= TYPE_VTOL_FIRST && vehicle_type <= TYPE_VTOL_LAST);
+  const uint32_t mode_auto = plane ? PLANE_MODE_AUTO : COPTER_MODE_AUTO;
+  const uint32_t mode_guided = plane ? PLANE_MODE_GUIDED : COPTER_MODE_GUIDED;
+
+  const mavsdk::Telemetry::Result set_rate_result = telemetry.set_rate_position(rates->position);
   if (set_rate_result != mavsdk::Telemetry::Result::Success) {
     cb_log(rust_cb, "Failed to connect to system");
-    return -1;
+    return RUN_FAILED;
   }
 
   cb_log(rust_cb, "Setting up Position monitoring");
   telemetry.subscribe_position([&](mavsdk::Telemetry::Position position) {
     cb_pos(rust_cb, position.latitude_deg, position.longitude_deg, position.relative_altitude_m);
   });
+  subscribe_telemetry(telemetry, rates, stream, cb_log);
+
+  // keep the preflight checklist current until the vehicle arms
+  telemetry.subscribe_health([&](mavsdk::Telemetry::Health) {
+    const auto report = read_health(telemetry, ardupilot);
+    cb_health(rust_cb, &report);
+  });
+  const auto report = read_health(telemetry, ardupilot);
+  cb_health(rust_cb, &report);
 
-  // check for vehicle to be ready to arm
-  while (telemetry.health_all_ok()) {
-    cb_log(rust_cb, "Waiting for vehicle to arm");
+  // check for vehicle to be ready to arm, a vehicle that never is fails instead of blocking
+  // ArduPilot does not report sensor calibration through health, only wait for a position
+  const auto healthy = [&]() {
+    const auto health = telemetry.health();
+    return ardupilot ? health.is_global_position_ok && health.is_home_position_ok
+                     : telemetry.health_all_ok();
+  };
+  for (int waited = 0; !healthy(); waited++) {
+    if (waited >= HEALTH_TIMEOUT_SEC) {
+      const auto msg = string_format("Health checks did not pass in %d s", HEALTH_TIMEOUT_SEC);
+      cb_log(rust_cb, msg.c_str());
+      return RUN_FAILED;
+    }
+    cb_log(rust_cb, ardupilot ? "Waiting for vehicle position and home"
+                              : "Waiting for vehicle health checks to pass");
     sleep_for(seconds(1));
   }
 
-  cb_log(rust_cb, "Pulling mission data from plan file");
-  auto import_plan = mission_raw.import_qgroundcontrol_mission(path);
-  if (import_plan.first != mavsdk::MissionRaw::Result::Success) {
-    cb_log(rust_cb, string_format("Failed to import mission: {}", import_plan.first).c_str());
-    return -1;
-  }
-  if (import_plan.second.mission_items.empty()) {
+  // the items are copied, the handle stays as rust built it
+  auto mission_items = *items;
+  if (mission_items.empty()) {
     cb_log(rust_cb, "Mission is empty");
-    return -1;
+    return RUN_UPLOAD_FAILED;
+  }
+
+  if (ardupilot) {
+    insert_ardupilot_home(mission_items);
   }
 
   cb_log(rust_cb, "Uploading mission to system");
-  const auto upload_result = mission_raw.upload_mission(import_plan.second.mission_items);
+  const auto upload_result = mission_raw.upload_mission(mission_items);
   if (upload_result != mavsdk::MissionRaw::Result::Success) {
     cb_log(rust_cb, string_format("Failed to upload mission to system: {}", upload_result).c_str());
-    return -1;
+    return RUN_UPLOAD_FAILED;
   }
   cb_log(rust_cb, "Successfully uploaded mission");
 
+  // the vehicle only arms once it is known to hold the mission that was planned
+  cb_log(rust_cb, "Verifying mission on system");
+  const auto download = mission_raw.download_mission();
+  if (download.first != mavsdk::MissionRaw::Result::Success) {
+    cb_log(rust_cb, "Failed to download mission for verification");
+    return RUN_VERIFY_FAILED;
+  }
+  if (!verify_mission(mission_items,
+                      download.second,
+                      ardupilot,
+                      rust_cb,
+                      cb_mismatch)) {
+    cb_log(rust_cb, "Mission on system differs from the plan, not arming");
+    return RUN_VERIFY_FAILED;
+  }
+  cb_log(rust_cb, "Mission verified");
+
+  // wait for the rest of the fleet so every vehicle arms and starts together
+  if (!cb_ready(rust_cb)) {
+    return RUN_NOT_STARTED;
+  }
+
+  // ArduPilot arms in GUIDED and starts the mission from AUTO
+  if (ardupilot && !set_ardupilot_mode(passthrough, mode_guided)) {
+    cb_log(rust_cb, "Failed to switch to GUIDED");
+    return RUN_ARM_FAILED;
+  }
+
   cb_log(rust_cb, "Arming system");
   const auto arm_result = action.arm();
   if (arm_result != mavsdk::Action::Result::Success) {
     cb_log(rust_cb, string_format("Arm Failed: {}", arm_result).c_str());
+    return RUN_ARM_FAILED;
   }
   cb_log(rust_cb, "Arming complete");
+  telemetry.subscribe_health(nullptr);
 
+  std::atomic<int32_t> current_item{0};
+  std::atomic<int32_t> total_items{0};
   mission_raw.subscribe_mission_progress([&](mavsdk::MissionRaw::MissionProgress mission_progress) {
+    current_item = mission_progress.current;
+    total_items = mission_progress.total;
     cb_pro(rust_cb, mission_progress.current, mission_progress.total);
   });
 
+  std::atomic<bool> failsafe{false};
+  // replaces the status text subscription, so it keeps forwarding the text
+  telemetry.subscribe_status_text([&](mavsdk::Telemetry::StatusText status_text) {
+    stream.status_text(status_text);
+    if (status_text.text.find("ailsafe") != std::string::npos && !failsafe.exchange(true)) {
+      cb_log(rust_cb, status_text.text.c_str());
+      cb_fail(rust_cb, FAILURE_FAILSAFE);
+    }
+  });
+
   cb_log(rust_cb, "Starting Mission");
+  if (ardupilot) {
+    if (!set_ardupilot_mode(passthrough, mode_auto)
+        || !send_command(passthrough, CMD_MISSION_START, 0, 0)) {
+      cb_log(rust_cb, "Mission start failed: could not switch to AUTO");
+      return RUN_ARM_FAILED;
+    }
+  } else {
+    const auto start_result = mission_raw.start_mission();
+    if (start_result != mavsdk::MissionRaw::Result::Success) {
+      cb_log(rust_cb, string_format("Mission start failed: {}", start_result).c_str());
+      return RUN_ARM_FAILED;
+    }
+  }
+
+  // watch the flight until the vehicle disarms, the plugins have to outlive the subscriptions
+  bool link_lost = false;
+  while (telemetry.armed() || link_lost) {
+    if (!system->is_connected() && !link_lost) {
+      cb_log(rust_cb, "Lost link to vehicle");
+      cb_fail(rust_cb, FAILURE_LINK_LOST);
+    }
+    link_lost = !system->is_connected();
+
+    // returning or landing before the last item is a failsafe unless flightctl commanded it,
+    // which the failure callback tells apart
+    const auto mode = telemetry.flight_mode();
+    const bool returning = mode == mavsdk::Telemetry::FlightMode::ReturnToLaunch
+        || mode == mavsdk::Telemetry::FlightMode::Land;
+    if (returning && current_item + 1 < total_items && !failsafe.exchange(true)) {
+      cb_log(rust_cb, "Vehicle left the mission to return or land");
+      cb_fail(rust_cb, FAILURE_FAILSAFE);
+    } else if (mode == mavsdk::Telemetry::FlightMode::Mission) {
+      // back on the mission after a resume, the next failsafe is reported again
+      failsafe = false;
+    }
+    sleep_for(seconds(1));
+  }
+  cb_com(rust_cb);
+  return 0;
+}
+
+// to_raw_item copies a MissionRaw item or MISSION_ITEM_INT, which share their field names
+template<typename T>
+static RawItem to_raw_item(uint8_t mission_type, T const &mission_item) {
+  RawItem item{};
+  item.mission_type = mission_type;
+  item.seq = mission_item.seq;
+  item.frame = mission_item.frame;
+  item.command = mission_item.command;
+  item.autocontinue = mission_item.autocontinue != 0;
+  item.param1 = mission_item.param1;
+  item.param2 = mission_item.param2;
+  item.param3 = mission_item.param3;
+  item.param4 = mission_item.param4;
+  item.x = mission_item.x;
+  item.y = mission_item.y;
+  item.z = mission_item.z;
+  return item;
+}
+
+// mission item handles let rust build a mission in memory and upload it without a plan file
+MRHandle new_mission_items() {
+  return new std::vector<mavsdk::MissionRaw::MissionItem>();
+}
+
+void del_mission_items(MRHandle items) {
+  delete items;
+}
+
+// add_mission_item appends an item to the mission, numbering it and making the first current
+void add_mission_item(MRHandle items, RawItem const *item) {
+  mavsdk::MissionRaw::MissionItem mission_item{};
+  mission_item.seq = static_cast<uint32_t>(items->size());
+  mission_item.frame = item->frame;
+  mission_item.command = item->command;
+  mission_item.current = items->empty() ? 1 : 0;
+  mission_item.autocontinue = item->autocontinue ? 1 : 0;
+  mission_item.param1 = item->param1;
+  mission_item.param2 = item->param2;
+  mission_item.param3 = item->param3;
+  mission_item.param4 = item->param4;
+  mission_item.x = item->x;
+  mission_item.y = item->y;
+  mission_item.z = item->z;
+  mission_item.mission_type = MISSION_TYPE_MISSION;
+  items->push_back(mission_item);
+}
+
+// pull_plan downloads the mission, geofence and rally points of a vehicle, passing every item
+// to cb_item as it is, and fills in the vehicle's autopilot, airframe and home
+int32_t pull_plan(SHandle system, void *rust_cb, rust_cb_pull_item cb_item, PulledVehicle *vehicle) {
+  auto telemetry = mavsdk::Telemetry{*system};
+  auto mission_raw = mavsdk::MissionRaw{*system};
+  auto passthrough = mavsdk::MavlinkPassthrough{*system};
+
+  auto heartbeat_promise = std::promise<mavlink_heartbeat_t>{};
+  auto heartbeat_future = heartbeat_promise.get_future();
+  std::atomic<bool> heard{false};
+  passthrough.subscribe_message_async(MAVLINK_MSG_ID_HEARTBEAT,
+                                      [&](const mavlink_message_t &message) {
+    if (message.sysid == passthrough.get_target_sysid() && !heard.exchange(true)) {
+      mavlink_heartbeat_t heartbeat;
+      mavlink_msg_heartbeat_decode(&message, &heartbeat);
+      heartbeat_promise.set_value(heartbeat);
+    }
+  });
+  if (heartbeat_future.wait_for(seconds(3)) == std::future_status::timeout) {
+    passthrough.subscribe_message_async(MAVLINK_MSG_ID_HEARTBEAT, nullptr);
+    std::cerr << "No heartbeat from vehicle\n";
+    return -1;
+  }
+  passthrough.subscribe_message_async(MAVLINK_MSG_ID_HEARTBEAT, nullptr);
+  const auto heartbeat = heartbeat_future.get();
+  vehicle->autopilot = heartbeat.autopilot;
+  vehicle->vehicle_type = heartbeat.type;
+
+  const auto home = telemetry.home();
+  vehicle->home_lat = home.latitude_deg;
+  vehicle->home_lon = home.longitude_deg;
+  vehicle->home_alt = home.absolute_altitude_m;
+
+  const auto download = mission_raw.download_mission();
+  if (download.first != mavsdk::MissionRaw::Result::Success) {
+    std::cerr << "Mission download failed: " << download.first << '\n';
+    return -1;
+  }
+  for (const auto &mission_item : download.second) {
+    const auto item = to_raw_item(MISSION_TYPE_MISSION, mission_item);
+    cb_item(rust_cb, &item);
+  }
+
+  for (const uint8_t mission_type : {MISSION_TYPE_FENCE, MISSION_TYPE_RALLY}) {
+    std::vector<mavlink_mission_item_int_t> items;
+    if (!download_items(passthrough, mission_type, items)) {
+      std::cerr << (mission_type == MISSION_TYPE_FENCE ? "Geofence" : "Rally point")
+                << " download failed\n";
+      return -1;
+    }
+    for (const auto &mission_item : items) {
+      const auto item = to_raw_item(mission_type, mission_item);
+      cb_item(rust_cb, &item);
+    }
+  }
+  return 0;
+}
+
+// polygons are flattened into lats/lons, sizes holds the number of points in each polygon
+int32_t upload_geofence(SHandle system,
+                        double_t const *lats,
+                        double_t const *lons,
+                        int32_t const *sizes,
+                        bool const *inclusions,
+                        int32_t count) {
+  auto geofence = mavsdk::Geofence{*system};
+
+  std::vector<mavsdk::Geofence::Polygon> polygons;
+  int32_t offset = 0;
+  for (int32_t i = 0; i < count; i++) {
+    mavsdk::Geofence::Polygon polygon;
+    polygon.fence_type = inclusions[i] ? mavsdk::Geofence::FenceType::Inclusion
+                                       : mavsdk::Geofence::FenceType::Exclusion;
+    for (int32_t j = 0; j < sizes[i]; j++) {
+      mavsdk::Geofence::Point point;
+      point.latitude_deg = lats[offset + j];
+      point.longitude_deg = lons[offset + j];
+      polygon.points.push_back(point);
+    }
+    offset += sizes[i];
+    polygons.push_back(polygon);
+  }
+
+  const auto upload_result = geofence.upload_geofence(polygons);
+  if (upload_result != mavsdk::Geofence::Result::Success) {
+    std::cerr << "Geofence upload failed: " << upload_result << '\n';
+    return -1;
+  }
+  return 0;
+}
+
+int32_t action_hold(SHandle system) {
+  auto action = mavsdk::Action{*system};
+  const auto hold_result = action.hold();
+  if (hold_result != mavsdk::Action::Result::Success) {
+    std::cerr << "Hold failed: " << hold_result << '\n';
+    return -1;
+  }
+  return 0;
+}
+
+int32_t action_land(SHandle system) {
+  auto action = mavsdk::Action{*system};
+  const auto land_result = action.land();
+  if (land_result != mavsdk::Action::Result::Success) {
+    std::cerr << "Land failed: " << land_result << '\n';
+    return -1;
+  }
+  return 0;
+}
+
+// kill stops the motors at once, the vehicle falls if it is in the air
+int32_t action_kill(SHandle system) {
+  auto action = mavsdk::Action{*system};
+  const auto kill_result = action.kill();
+  if (kill_result != mavsdk::Action::Result::Success) {
+    std::cerr << "Kill failed: " << kill_result << '\n';
+    return -1;
+  }
+  return 0;
+}
+
+// resume continues the uploaded mission from its current item after a hold
+int32_t action_resume(SHandle system, int32_t firmware, int32_t vehicle_type) {
+  if (firmware == AUTOPILOT_ARDUPILOT) {
+    auto passthrough = mavsdk::MavlinkPassthrough{*system};
+    const bool plane = vehicle_type == TYPE_FIXED_WING
+        || (vehicle_type >= TYPE_VTOL_FIRST && vehicle_type <= TYPE_VTOL_LAST);
+    if (!set_ardupilot_mode(passthrough, plane ? PLANE_MODE_AUTO : COPTER_MODE_AUTO)) {
+      std::cerr << "Resume failed: could not switch to AUTO\n";
+      return -1;
+    }
+    return 0;
+  }
+  auto mission_raw = mavsdk::MissionRaw{*system};
   const auto start_result = mission_raw.start_mission();
   if (start_result != mavsdk::MissionRaw::Result::Success) {
-    cb_log(rust_cb, string_format("Mission start failed: {}", start_result).c_str());
+    std::cerr << "Resume failed: " << start_result << '\n';
+    return -1;
+  }
+  return 0;
+}
+
+// pause holds the vehicle on the mission, resume continues from the current item
+int32_t mission_pause(SHandle system, int32_t firmware, int32_t vehicle_type) {
+  if (firmware == AUTOPILOT_ARDUPILOT) {
+    // GUIDED without a target holds a copter in place and circles a plane where it is
+    auto passthrough = mavsdk::MavlinkPassthrough{*system};
+    const bool plane = vehicle_type == TYPE_FIXED_WING
+        || (vehicle_type >= TYPE_VTOL_FIRST && vehicle_type <= TYPE_VTOL_LAST);
+    if (!set_ardupilot_mode(passthrough, plane ? PLANE_MODE_GUIDED : COPTER_MODE_GUIDED)) {
+      std::cerr << "Pause failed: could not switch to GUIDED\n";
+      return -1;
+    }
+    return 0;
+  }
+  auto mission_raw = mavsdk::MissionRaw{*system};
+  const auto pause_result = mission_raw.pause_mission();
+  if (pause_result != mavsdk::MissionRaw::Result::Success) {
+    std::cerr << "Pause failed: " << pause_result << '\n';
+    return -1;
+  }
+  return 0;
+}
+
+// item counts from the first plan item, ArduPilot holds home before it
+int32_t mission_set_current(SHandle system, int32_t firmware, int32_t item) {
+  auto mission_raw = mavsdk::MissionRaw{*system};
+  const int32_t index = firmware == AUTOPILOT_ARDUPILOT ? item + 1 : item;
+  const auto set_result = mission_raw.set_current_mission_item(index);
+  if (set_result != mavsdk::MissionRaw::Result::Success) {
+    std::cerr << "Setting current mission item failed: " << set_result << '\n';
+    return -1;
+  }
+  return 0;
+}
+
+// altitude is relative to home unless amsl is set
+int32_t action_goto(SHandle system, double_t lat, double_t lon, float_t altitude, bool amsl) {
+  auto action = mavsdk::Action{*system};
+  auto telemetry = mavsdk::Telemetry{*system};
+  const float_t altitude_amsl = amsl ? altitude : telemetry.home().absolute_altitude_m + altitude;
+  const auto goto_result = action.goto_location(lat, lon, altitude_amsl, NAN);
+  if (goto_result != mavsdk::Action::Result::Success) {
+    std::cerr << "Goto failed: " << goto_result << '\n';
+    return -1;
+  }
+  return 0;
+}
+
+int32_t action_return_to_launch(SHandle system) {
+  auto action = mavsdk::Action{*system};
+  const auto rtl_result = action.return_to_launch();
+  if (rtl_result != mavsdk::Action::Result::Success) {
+    std::cerr << "Return to launch failed: " << rtl_result << '\n';
+    return -1;
+  }
+  return 0;
+}
+
+// altitude is in meters above home, ArduPilot stores it in centimeters
+int32_t set_return_altitude(SHandle system,
+                            int32_t firmware,
+                            int32_t vehicle_type,
+                            float_t altitude) {
+  auto param = mavsdk::Param{*system};
+  const bool plane = vehicle_type == TYPE_FIXED_WING
+      || (vehicle_type >= TYPE_VTOL_FIRST && vehicle_type <= TYPE_VTOL_LAST);
+
+  mavsdk::Param::Result result;
+  if (firmware == AUTOPILOT_ARDUPILOT) {
+    const auto name = plane ? "ALT_HOLD_RTL" : "RTL_ALT";
+    result = param.set_param_int(name, static_cast<int32_t>(altitude * 100));
+  } else {
+    result = param.set_param_float("RTL_RETURN_ALT", altitude);
+  }
+  if (result != mavsdk::Param::Result::Success) {
+    std::cerr << "Setting return altitude failed: " << result << '\n';
     return -1;
   }
-  cb_com(rust_cb);
   return 0;
 }
 }
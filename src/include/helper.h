@@ -11,15 +11,98 @@
 #include <mavsdk/mavsdk.h>
 #include <mavsdk/plugins/telemetry/telemetry.h>
 #include <mavsdk/plugins/action/action.h>
+#include <mavsdk/plugins/geofence/geofence.h>
+#include <mavsdk/plugins/mavlink_passthrough/mavlink_passthrough.h>
 #include <mavsdk/plugins/mission/mission.h>
 #include <mavsdk/plugins/mission_raw/mission_raw.h>
+#include <mavsdk/plugins/param/param.h>
 
 // Callback code
 extern "C" {
+// HealthReport is the preflight health of a vehicle, broken out for the checklist
+struct HealthReport {
+  // MAVSDK Telemetry::FixType, 3 and above is a 3D fix
+  int32_t gps_fix_type;
+  int32_t satellites;
+  bool home_ok;
+  bool gyro_ok;
+  bool accel_ok;
+  bool mag_ok;
+  // remaining battery in percent, NaN when not reported
+  float_t battery_percent;
+  bool armable;
+};
+
+// TelemetryReport is the latest value of every telemetry stream of a vehicle
+struct TelemetryReport {
+  // remaining battery in percent, NaN when not reported
+  float_t battery_percent;
+  float_t battery_voltage;
+  // MAVSDK Telemetry::FlightMode
+  int32_t flight_mode;
+  bool armed;
+  bool in_air;
+  // MAVSDK Telemetry::FixType
+  int32_t gps_fix_type;
+  int32_t satellites;
+  float_t heading_deg;
+  float_t velocity_north;
+  float_t velocity_east;
+  float_t velocity_down;
+  bool rc_available;
+  float_t rc_signal_percent;
+};
+
+// RawItem is a mission, fence or rally item as sent to or pulled from a vehicle, laid out as
+// MISSION_ITEM_INT
+struct RawItem {
+  // MAV_MISSION_TYPE, 0 mission, 1 fence, 2 rally
+  int32_t mission_type;
+  uint32_t seq;
+  uint32_t frame;
+  uint32_t command;
+  bool autocontinue;
+  float_t param1;
+  float_t param2;
+  float_t param3;
+  float_t param4;
+  int32_t x;
+  int32_t y;
+  float_t z;
+};
+
+// PulledVehicle is the autopilot, airframe and home of a vehicle the plan is pulled from
+struct PulledVehicle {
+  // MAV_AUTOPILOT and MAV_TYPE from the vehicle's heartbeat
+  int32_t autopilot;
+  int32_t vehicle_type;
+  double_t home_lat;
+  double_t home_lon;
+  // home altitude above mean sea level, NaN when the vehicle has no home
+  float_t home_alt;
+};
+
+// TelemetryRates is the rate in Hz requested for each telemetry stream, heading comes with
+// the position, flight mode, armed state and status text are sent as they change
+struct TelemetryRates {
+  double_t position;
+  double_t battery;
+  double_t gps_info;
+  double_t in_air;
+  double_t velocity;
+  double_t rc_status;
+};
+
 typedef void (*rust_cb_log)(void *, char const *);
 typedef void (*rust_cb_run_position)(void *, double_t, double_t, float_t);
 typedef void (*rust_cb_run_progress)(void *, int32_t, int32_t);
 typedef void (*rust_cb_run_complete)(void *);
+typedef bool (*rust_cb_run_ready)(void *);
+typedef void (*rust_cb_run_failure)(void *, int32_t);
+typedef void (*rust_cb_run_health)(void *, HealthReport const *);
+typedef void (*rust_cb_pull_item)(void *, RawItem const *);
+typedef void (*rust_cb_run_mismatch)(void *, int32_t, char const *);
+typedef void (*rust_cb_run_telemetry)(void *, int32_t, TelemetryReport const *, char const *);
 };
 
 // Shim code
@@ -33,14 +116,41 @@ void del_mavsdk(SDKHandle);
 // Helper functions
 extern "C" {
 typedef std::vector<mavsdk::MissionRaw::MissionItem> *MRHandle;
-SHandle connect(SDKHandle, char const *);
+SHandle connect(SDKHandle, char const *, int32_t);
+MRHandle new_mission_items();
+void del_mission_items(MRHandle);
+void add_mission_item(MRHandle, RawItem const *);
 int32_t run_qgc_plan(SHandle,
-                     char const *,
+                     MRHandle,
                      void *,
                      rust_cb_run_position,
                      rust_cb_run_progress,
                      rust_cb_run_complete,
-                     rust_cb_log);
+                     rust_cb_log,
+                     rust_cb_run_ready,
+                     rust_cb_run_failure,
+                     rust_cb_run_health,
+                     rust_cb_run_telemetry,
+                     rust_cb_run_mismatch,
+                     TelemetryRates const *,
+                     int32_t,
+                     int32_t);
+int32_t pull_plan(SHandle, void *, rust_cb_pull_item, PulledVehicle *);
+int32_t upload_geofence(SHandle,
+                        double_t const *,
+                        double_t const *,
+                        int32_t const *,
+                        bool const *,
+                        int32_t);
+int32_t action_hold(SHandle);
+int32_t action_return_to_launch(SHandle);
+int32_t action_land(SHandle);
+int32_t action_kill(SHandle);
+int32_t action_resume(SHandle, int32_t, int32_t);
+int32_t mission_pause(SHandle, int32_t, int32_t);
+int32_t mission_set_current(SHandle, int32_t, int32_t);
+int32_t action_goto(SHandle, double_t, double_t, float_t, bool);
+int32_t set_return_altitude(SHandle, int32_t, int32_t, float_t);
 };
 
 // String format function
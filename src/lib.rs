@@ -1,6 +1,9 @@
+// the callbacks are only called by helper.cc, with the pointers it was given or owns
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
 use std::ffi::CStr;
 
-use libc::c_char;
+use libc::{c_char, c_void};
 
 #[repr(C)]
 pub struct SDKHandle {
@@ -26,25 +29,173 @@ pub struct MRHandle {
     _marker: core::marker::PhantomData<(*mut u8, core::marker::PhantomPinned)>,
 }
 
+// HealthReport is the preflight health of a vehicle, matching the HealthReport in helper.h
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct HealthReport {
+    // MAVSDK Telemetry::FixType, 3 and above is a 3D fix
+    pub gps_fix_type: i32,
+    pub satellites: i32,
+    pub home_ok: bool,
+    pub gyro_ok: bool,
+    pub accel_ok: bool,
+    pub mag_ok: bool,
+    // remaining battery in percent, NaN when not reported
+    pub battery_percent: f32,
+    pub armable: bool,
+}
+
+// TelemetryReport is the latest value of every telemetry stream, matching helper.h
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct TelemetryReport {
+    // remaining battery in percent, NaN when not reported
+    pub battery_percent: f32,
+    pub battery_voltage: f32,
+    // MAVSDK Telemetry::FlightMode
+    pub flight_mode: i32,
+    pub armed: bool,
+    pub in_air: bool,
+    // MAVSDK Telemetry::FixType
+    pub gps_fix_type: i32,
+    pub satellites: i32,
+    pub heading_deg: f32,
+    pub velocity_north: f32,
+    pub velocity_east: f32,
+    pub velocity_down: f32,
+    pub rc_available: bool,
+    pub rc_signal_percent: f32,
+}
+
+// telemetry stream that changed, passed with each report
+pub const TELEMETRY_BATTERY: i32 = 0;
+pub const TELEMETRY_GPS_INFO: i32 = 4;
+pub const TELEMETRY_STATUS_TEXT: i32 = 8;
+
+// RawItem is a mission, fence or rally item as sent to or pulled from a vehicle, matching
+// helper.h
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct RawItem {
+    // MAV_MISSION_TYPE, 0 mission, 1 fence, 2 rally
+    pub mission_type: i32,
+    pub seq: u32,
+    pub frame: u32,
+    pub command: u32,
+    pub autocontinue: bool,
+    pub param1: f32,
+    pub param2: f32,
+    pub param3: f32,
+    pub param4: f32,
+    pub x: i32,
+    pub y: i32,
+    pub z: f32,
+}
+
+// PulledVehicle is the autopilot, airframe and home of a pulled vehicle, matching helper.h
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PulledVehicle {
+    // MAV_AUTOPILOT and MAV_TYPE from the vehicle's heartbeat
+    pub autopilot: i32,
+    pub vehicle_type: i32,
+    pub home_lat: f64,
+    pub home_lon: f64,
+    // home altitude above mean sea level, NaN when the vehicle has no home
+    pub home_alt: f32,
+}
+
+// TelemetryRates is the rate in Hz requested for each telemetry stream, matching helper.h
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct TelemetryRates {
+    pub position: f64,
+    pub battery: f64,
+    pub gps_info: f64,
+    pub in_air: f64,
+    pub velocity: f64,
+    pub rc_status: f64,
+}
+
+impl Default for TelemetryRates {
+    fn default() -> Self {
+        TelemetryRates {
+            position: 1.0,
+            battery: 1.0,
+            gps_info: 1.0,
+            in_air: 1.0,
+            velocity: 2.0,
+            rc_status: 1.0,
+        }
+    }
+}
+
+// the callback context is a pointer to a boxed trait object, C++ only passes it back
+#[allow(improper_ctypes)]
 extern "C" {
     // shim wrappers
-    pub fn new_mavsdk() -> SDKHandle;
-    pub fn del_mavsdk(p: SDKHandle);
+    pub fn new_mavsdk() -> *mut SDKHandle;
+    pub fn del_mavsdk(p: *mut SDKHandle);
 
     // helper wrappers
-    pub fn connect(p: SDKHandle, addr: *const c_char) -> SHandle;
+    pub fn connect(p: *mut SDKHandle, addr: *const c_char, timeout: i32) -> *mut SHandle;
+    pub fn new_mission_items() -> *mut MRHandle;
+    pub fn del_mission_items(items: *mut MRHandle);
+    pub fn add_mission_item(items: *mut MRHandle, item: *const RawItem);
     pub fn run_qgc_plan(
-        system: SHandle,
-        path: *const c_char,
+        system: *mut SHandle,
+        items: *mut MRHandle,
         cb_context: *mut Box<dyn RunCallBackObject>,
         cb_position: extern "C" fn(*mut Box<dyn RunCallBackObject>, f64, f64, f32),
         cb_progress: extern "C" fn(*mut Box<dyn RunCallBackObject>, i32, i32),
         cb_complete: extern "C" fn(*mut Box<dyn RunCallBackObject>),
         cb_log: extern "C" fn(*mut Box<dyn RunCallBackObject>, *const c_char),
+        cb_ready: extern "C" fn(*mut Box<dyn RunCallBackObject>) -> bool,
+        cb_failure: extern "C" fn(*mut Box<dyn RunCallBackObject>, i32),
+        cb_health: extern "C" fn(*mut Box<dyn RunCallBackObject>, *const HealthReport),
+        cb_telemetry: extern "C" fn(
+            *mut Box<dyn RunCallBackObject>,
+            i32,
+            *const TelemetryReport,
+            *const c_char,
+        ),
+        cb_mismatch: extern "C" fn(*mut Box<dyn RunCallBackObject>, i32, *const c_char),
+        rates: *const TelemetryRates,
+        firmware: i32,
+        vehicle_type: i32,
+    ) -> i32;
+    pub fn pull_plan(
+        system: *mut SHandle,
+        cb_context: *mut c_void,
+        cb_item: extern "C" fn(*mut c_void, *const RawItem),
+        vehicle: *mut PulledVehicle,
+    ) -> i32;
+    pub fn upload_geofence(
+        system: *mut SHandle,
+        lats: *const f64,
+        lons: *const f64,
+        sizes: *const i32,
+        inclusions: *const bool,
+        count: i32,
+    ) -> i32;
+    pub fn action_hold(system: *mut SHandle) -> i32;
+    pub fn action_return_to_launch(system: *mut SHandle) -> i32;
+    pub fn action_land(system: *mut SHandle) -> i32;
+    pub fn action_kill(system: *mut SHandle) -> i32;
+    pub fn action_resume(system: *mut SHandle, firmware: i32, vehicle_type: i32) -> i32;
+    pub fn mission_pause(system: *mut SHandle, firmware: i32, vehicle_type: i32) -> i32;
+    pub fn mission_set_current(system: *mut SHandle, firmware: i32, item: i32) -> i32;
+    pub fn action_goto(system: *mut SHandle, lat: f64, lon: f64, altitude: f32, amsl: bool) -> i32;
+    pub fn set_return_altitude(
+        system: *mut SHandle,
+        firmware: i32,
+        vehicle_type: i32,
+        altitude: f32,
     ) -> i32;
 }
 
-// run mode callback wrappers
+// run mode callback wrappers, the context is borrowed, it is owned by the caller of
+// run_qgc_plan and freed once it returns
 pub extern "C" fn run_callback_position(
     context: *mut Box<dyn RunCallBackObject>,
     lat: f64,
@@ -52,7 +203,7 @@ pub extern "C" fn run_callback_position(
     alt: f32,
 ) {
     unsafe {
-        let cb: Box<Box<dyn RunCallBackObject>> = Box::from_raw(context);
+        let cb = &*context;
         cb.save_position(lat, lon, alt);
     }
 }
@@ -63,23 +214,80 @@ pub extern "C" fn run_callback_progress(
     total: i32,
 ) {
     unsafe {
-        let cb: Box<Box<dyn RunCallBackObject>> = Box::from_raw(context);
+        let cb = &*context;
         cb.save_progress(current, total);
     }
 }
 
 pub extern "C" fn run_callback_complete(context: *mut Box<dyn RunCallBackObject>) {
     unsafe {
-        let cb: Box<Box<dyn RunCallBackObject>> = Box::from_raw(context);
+        let cb = &*context;
         cb.complete();
     }
 }
 
 pub extern "C" fn run_callback_log(context: *mut Box<dyn RunCallBackObject>, msg: *const c_char) {
     unsafe {
-        let cb: Box<Box<dyn RunCallBackObject>> = Box::from_raw(context);
-        let c_str = CStr::from_ptr(msg).to_str().unwrap();
-        cb.log(c_str);
+        let cb = &*context;
+        let msg = CStr::from_ptr(msg).to_string_lossy();
+        cb.log(&msg);
+    }
+}
+
+pub extern "C" fn run_callback_ready(context: *mut Box<dyn RunCallBackObject>) -> bool {
+    unsafe {
+        let cb = &*context;
+        cb.ready()
+    }
+}
+
+pub extern "C" fn run_callback_failure(context: *mut Box<dyn RunCallBackObject>, kind: i32) {
+    unsafe {
+        let cb = &*context;
+        cb.failed(kind);
+    }
+}
+
+pub extern "C" fn run_callback_health(
+    context: *mut Box<dyn RunCallBackObject>,
+    health: *const HealthReport,
+) {
+    unsafe {
+        let cb = &*context;
+        cb.save_health(&*health);
+    }
+}
+
+pub extern "C" fn run_callback_telemetry(
+    context: *mut Box<dyn RunCallBackObject>,
+    stream: i32,
+    report: *const TelemetryReport,
+    text: *const c_char,
+) {
+    unsafe {
+        let cb = &*context;
+        let text = CStr::from_ptr(text).to_string_lossy();
+        cb.save_telemetry(stream, &*report, &text);
+    }
+}
+
+pub extern "C" fn run_callback_mismatch(
+    context: *mut Box<dyn RunCallBackObject>,
+    item: i32,
+    reason: *const c_char,
+) {
+    unsafe {
+        let cb = &*context;
+        let reason = CStr::from_ptr(reason).to_string_lossy();
+        cb.mismatch(item, &reason);
+    }
+}
+
+// pull callback, the context is the Vec<RawItem> collecting every item downloaded
+pub extern "C" fn pull_callback_item(context: *mut c_void, item: *const RawItem) {
+    unsafe {
+        let items = &mut *(context as *mut Vec<RawItem>);
+        items.push(*item);
     }
 }
 
@@ -87,6 +295,16 @@ pub extern "C" fn run_callback_log(context: *mut Box<dyn RunCallBackObject>, msg
 pub trait RunCallBackObject {
     fn save_position(&self, lat: f64, lon: f64, alt: f32);
     fn save_progress(&self, current: i32, total: i32);
+    fn save_health(&self, health: &HealthReport);
+    // save_telemetry receives the whole report on every update, text is only set for the
+    // status text stream
+    fn save_telemetry(&self, stream: i32, report: &TelemetryReport, text: &str);
     fn log(&self, msg: &str);
+    // mismatch reports a mission item that differs after upload, -1 for the item count
+    fn mismatch(&self, item: i32, reason: &str);
+    // ready blocks once the plan is uploaded until the vehicle may arm and start
+    fn ready(&self) -> bool;
+    // failed reports a failure code once the mission is running
+    fn failed(&self, kind: i32);
     fn complete(&self);
 }
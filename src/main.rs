@@ -1,15 +1,34 @@
 extern crate core;
 
-use crate::generate::{CircleMission, LineMission, ShapeMission, SquareMission};
+use crate::airspace::Airspace;
+use crate::generate::{
+    AltitudeMode, CircleMission, Facing, Firmware, InspectionMission, LandingSchedule, LineMission,
+    PayloadAction, PlanOptions, PlanProfile, PointsMission, ShapeMission, SquareMission,
+    SurveyMission, VehicleType,
+};
+use crate::geofence::Fence;
+use crate::manifest::Manifest;
+use crate::run_mode::{FailurePolicy, FenceAction, RunOptions, TelemetryRate};
+use crate::terrain::Terrain;
 use clap::{Args, Parser, Subcommand};
+use mavsdk::TelemetryRates;
 use std::error::Error;
+use std::io;
 use std::path::Path;
 use std::time::Duration;
 
+mod airspace;
 mod app;
 mod generate;
+mod geofence;
+mod manifest;
+mod mtsp;
+mod points;
+mod pull;
 mod run_mode;
+mod terrain;
 mod ui;
+mod validate;
 
 #[derive(Debug, Parser)]
 #[clap(name=env!("CARGO_CRATE_NAME"))]
@@ -26,6 +45,8 @@ struct Cli {
     command: Commands,
 }
 
+// parsed once at startup, so the size of the largest command does not matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Subcommand)]
 enum Commands {
     #[clap(about = "Start FlightCTL Terminal User Interface in UI mode")]
@@ -33,6 +54,8 @@ enum Commands {
     UI {},
     Run(Run),
     Generate(Generate),
+    Validate(Validate),
+    Pull(Pull),
     Echo(Echo),
 }
 
@@ -41,12 +64,101 @@ enum Commands {
 #[clap(arg_required_else_help = true)]
 struct Run {
     #[clap(help = "One or more Uri to a vechicle's MavSDK Interface")]
-    #[clap(short = 'v')]
+    #[clap(short = 'v', conflicts_with = "manifest")]
     vehicles: Vec<String>,
 
     #[clap(help = "One or more .plan files to direct the corresponding drones")]
-    #[clap(short = 'p')]
+    #[clap(short = 'p', conflicts_with = "manifest")]
     plans: Vec<String>,
+
+    #[clap(long = "shape", conflicts_with_all = &["plans", "manifest"])]
+    #[clap(multiple_values = true, allow_hyphen_values = true)]
+    #[clap(help = "Generate and fly plans, the rest of the line is a generate command")]
+    shape: Vec<String>,
+
+    #[clap(long = "manifest")]
+    #[clap(help = "Path to a TOML manifest of the vehicles, plans and run policy")]
+    manifest: Option<String>,
+
+    #[clap(long = "timeout", conflicts_with = "manifest", default_value = "3")]
+    #[clap(help = "Seconds to wait for each vehicle to be discovered")]
+    timeout: u32,
+
+    #[clap(long = "t-zero", conflicts_with = "manifest")]
+    #[clap(help = "Unix time in seconds to start every vehicle at, instead of pressing s")]
+    t_zero: Option<u64>,
+
+    #[clap(long = "on-failure", conflicts_with = "manifest")]
+    #[clap(arg_enum, default_value = "continue")]
+    #[clap(help = "Fleet action when a vehicle fails to upload or arm, loses link or fails safe")]
+    failure_policy: FailurePolicy,
+
+    #[clap(long = "fence", conflicts_with = "manifest")]
+    #[clap(help = "Path to a .plan or geofence file to upload instead of each plan's geofence")]
+    fence: Option<String>,
+
+    #[clap(long = "fence-action", conflicts_with = "manifest")]
+    #[clap(arg_enum, default_value = "rtl")]
+    #[clap(help = "Action commanded when flightctl sees a vehicle breach its geofence")]
+    fence_action: FenceAction,
+
+    #[clap(long = "rtl-alt", conflicts_with = "manifest")]
+    #[clap(help = "Return altitude of the first vehicle, overrides the plans")]
+    rtl_alt: Option<f32>,
+
+    #[clap(long = "rtl-alt-step", conflicts_with = "manifest")]
+    #[clap(default_value = "5")]
+    #[clap(help = "Meters each vehicle returns higher than the one before it with --rtl-alt")]
+    rtl_alt_step: f32,
+
+    #[clap(long = "min-battery", conflicts_with = "manifest")]
+    #[clap(default_value = "50")]
+    #[clap(help = "Battery percentage every vehicle needs to pass the preflight checklist")]
+    min_battery: f32,
+
+    #[clap(long = "rate", conflicts_with = "manifest")]
+    #[clap(help = "Telemetry stream rate in Hz, such as battery=2, repeat for each stream")]
+    telemetry_rates: Vec<TelemetryRate>,
+}
+
+impl Run {
+    // to_manifest loads the manifest, or builds one from the -v and -p lists or the plans
+    // generated for --shape
+    fn to_manifest(&self) -> io::Result<Manifest> {
+        if !self.shape.is_empty() {
+            let shape = ShapeArgs::try_parse_from(&self.shape)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+            let options = shape.plan.to_plan_options()?;
+            let plans = shape.command.to_shape_mission()?.generate_plans(&options)?;
+            return Manifest::from_generated(&self.vehicles, plans, self.to_run_options()?);
+        }
+        match &self.manifest {
+            Some(path) => Manifest::load(Path::new(path.as_str())),
+            None => Manifest::from_lists(&self.vehicles, &self.plans, self.to_run_options()?),
+        }
+    }
+
+    fn to_run_options(&self) -> io::Result<RunOptions> {
+        let fence = match &self.fence {
+            Some(path) => Some(Fence::load(Path::new(path.as_str()))?),
+            None => None,
+        };
+        let mut telemetry_rates = TelemetryRates::default();
+        for rate in &self.telemetry_rates {
+            rate.apply(&mut telemetry_rates);
+        }
+        Ok(RunOptions {
+            fence,
+            fence_action: self.fence_action,
+            return_alt: self.rtl_alt,
+            return_alt_step: self.rtl_alt_step,
+            timeout: self.timeout,
+            t_zero: self.t_zero,
+            failure_policy: self.failure_policy,
+            min_battery: self.min_battery,
+            telemetry_rates,
+        })
+    }
 }
 
 #[derive(Debug, Args)]
@@ -62,6 +174,312 @@ struct Generate {
     #[clap(short = 'p')]
     #[clap(help = "Path to a directory to save the plan files")]
     path: String,
+
+    #[clap(flatten)]
+    plan: PlanArgs,
+}
+
+// ShapeArgs parses the values of run --shape, a generate command line without the save path
+#[derive(Debug, Parser)]
+#[clap(name = "--shape", no_binary_name = true)]
+struct ShapeArgs {
+    #[clap(subcommand)]
+    command: GenerateCommands,
+
+    #[clap(flatten)]
+    plan: PlanArgs,
+}
+
+// PlanArgs are the plan options shared by generate and run --shape
+#[derive(Debug, Args)]
+struct PlanArgs {
+    #[clap(long = "fence-margin")]
+    #[clap(help = "Add an inclusion geofence around the paths buffered by this many meters")]
+    fence_margin: Option<f64>,
+
+    #[clap(long = "shared-fence")]
+    #[clap(help = "Use one inclusion geofence covering every vehicle instead of one per vehicle")]
+    shared_fence: bool,
+
+    #[clap(long = "exclusion")]
+    #[clap(help = "Path to a .plan or geofence file whose zones are added as exclusion zones")]
+    exclusion: Option<String>,
+
+    #[clap(long = "rally")]
+    #[clap(help = "Add each vehicle's launch point as a rally point")]
+    rally: bool,
+
+    #[clap(flatten)]
+    airspace: AirspaceArgs,
+
+    #[clap(long = "terrain")]
+    #[clap(help = "Path to an SRTM .hgt or GeoTIFF DEM tile, or a directory of tiles")]
+    terrain: Option<String>,
+
+    #[clap(long = "terrain-follow", requires = "terrain")]
+    #[clap(help = "Hold each waypoint's altitude above the terrain instead of above home")]
+    terrain_follow: bool,
+
+    #[clap(long = "min-clearance", requires = "terrain")]
+    #[clap(help = "Flag paths that come closer than this many meters to the terrain")]
+    min_clearance: Option<f64>,
+
+    #[clap(long = "face-center")]
+    #[clap(help = "Point every vehicle at the center of the formation")]
+    face_center: bool,
+
+    #[clap(long = "face-heading", conflicts_with = "face-center")]
+    #[clap(help = "Point every vehicle at this heading in degrees, such as toward the audience")]
+    face_heading: Option<f64>,
+
+    #[clap(long = "acceptance-radius")]
+    #[clap(help = "Distance in meters at which a waypoint counts as reached")]
+    acceptance_radius: Option<f64>,
+
+    #[clap(long = "pass-radius")]
+    #[clap(help = "Pass by waypoints at this radius in meters instead of through them")]
+    pass_radius: Option<f64>,
+
+    #[clap(flatten)]
+    profile: ProfileArgs,
+
+    #[clap(long = "turn-radius", default_value = "80")]
+    #[clap(help = "Fixed wing and VTOL turn radius in meters, used for loiters and turns")]
+    turn_radius: f64,
+
+    #[clap(long = "loiter-turns")]
+    #[clap(help = "Fly fixed wing holds as whole loiter turns instead of for the hold time")]
+    loiter_turns: bool,
+
+    #[clap(long = "land-heading", default_value = "0")]
+    #[clap(help = "Heading in degrees of the fixed wing landing approach")]
+    land_heading: f64,
+
+    #[clap(long = "land-sequence")]
+    #[clap(help = "Land each vehicle on its own pad in turn instead of returning to launch")]
+    land_sequence: bool,
+
+    #[clap(long = "land-pads")]
+    #[clap(help = "CSV or GeoJSON landing pads, one per vehicle in plan order")]
+    land_pads: Option<String>,
+
+    #[clap(long = "land-interval", default_value = "10")]
+    #[clap(help = "Seconds each vehicle holds over its pad longer than the one before it")]
+    land_interval: u16,
+
+    #[clap(long = "land-alt-step", default_value = "5")]
+    #[clap(help = "Meters each vehicle holds over its pad higher than the one before it")]
+    land_alt_step: u8,
+
+    #[clap(long = "rtl-alt")]
+    #[clap(help = "Return altitude of the first vehicle, set on the vehicle by run mode")]
+    rtl_alt: Option<u8>,
+
+    #[clap(long = "rtl-alt-step", default_value = "5")]
+    #[clap(help = "Meters each vehicle returns higher than the one before it")]
+    rtl_alt_step: u8,
+
+    #[clap(long = "return-path", requires = "rtl-alt")]
+    #[clap(help = "Fly home through waypoints at each vehicle's return altitude, needs --rtl-alt")]
+    return_path: bool,
+
+    #[clap(long = "repeat", default_value = "0")]
+    #[clap(help = "Fly the waypoints from --repeat-from to the last one this many more times")]
+    repeat: u16,
+
+    #[clap(long = "repeat-from", default_value = "1")]
+    #[clap(help = "Index of the first repeated waypoint, the takeoff is waypoint 0")]
+    repeat_from: usize,
+
+    #[clap(long = "waypoint-action")]
+    #[clap(help = "Payload action at every waypoint, such as digicam or servo:9:1900")]
+    waypoint_actions: Vec<PayloadAction>,
+
+    #[clap(long = "slot-action")]
+    #[clap(help = "Payload action at each vehicle's slot, such as gripper:1:release")]
+    slot_actions: Vec<PayloadAction>,
+}
+
+#[derive(Debug, Args)]
+struct ProfileArgs {
+    #[clap(long = "profile")]
+    #[clap(help = "Path to a json profile with the plan header settings")]
+    profile: Option<String>,
+
+    #[clap(long = "cruise-speed")]
+    #[clap(help = "Cruise speed in m/s, overrides the profile")]
+    cruise_speed: Option<f64>,
+
+    #[clap(long = "hover-speed")]
+    #[clap(help = "Hover speed in m/s, overrides the profile")]
+    hover_speed: Option<f64>,
+
+    #[clap(long = "firmware", arg_enum)]
+    #[clap(help = "Autopilot firmware the plans are for, overrides the profile")]
+    firmware: Option<Firmware>,
+
+    #[clap(long = "vehicle-type", arg_enum)]
+    #[clap(help = "Airframe the plans are for, overrides the profile")]
+    vehicle_type: Option<VehicleType>,
+
+    #[clap(long = "altitude-mode", arg_enum)]
+    #[clap(help = "Measure altitudes from home or from mean sea level, overrides the profile")]
+    altitude_mode: Option<AltitudeMode>,
+
+    #[clap(long = "takeoff-alt")]
+    #[clap(help = "Takeoff altitude, defaults to the altitude of the first waypoint")]
+    takeoff_alt: Option<u8>,
+}
+
+impl ProfileArgs {
+    fn to_profile(&self) -> io::Result<PlanProfile> {
+        let mut profile = match &self.profile {
+            Some(path) => PlanProfile::load(Path::new(path.as_str()))?,
+            None => PlanProfile::default(),
+        };
+        if let Some(speed) = self.cruise_speed {
+            profile.cruise_speed = speed;
+        }
+        if let Some(speed) = self.hover_speed {
+            profile.hover_speed = speed;
+        }
+        if let Some(firmware) = self.firmware {
+            profile.firmware = firmware;
+        }
+        if let Some(vehicle_type) = self.vehicle_type {
+            profile.vehicle_type = vehicle_type;
+        }
+        if let Some(altitude_mode) = self.altitude_mode {
+            profile.altitude_mode = altitude_mode;
+        }
+        if self.takeoff_alt.is_some() {
+            profile.takeoff_alt = self.takeoff_alt;
+        }
+        Ok(profile)
+    }
+}
+
+impl PlanArgs {
+    fn to_plan_options(&self) -> io::Result<PlanOptions> {
+        let mut exclusions = Fence::default();
+        if let Some(path) = &self.exclusion {
+            exclusions = Fence::load(Path::new(path.as_str()))?;
+            exclusions.set_inclusion(false);
+        }
+        let terrain = match &self.terrain {
+            Some(path) => Some(Terrain::load(Path::new(path.as_str()))?),
+            None => None,
+        };
+        let landing = if self.land_sequence || self.land_pads.is_some() {
+            let pads = match &self.land_pads {
+                Some(path) => points::load_points(Path::new(path.as_str()))?,
+                None => vec![],
+            };
+            Some(LandingSchedule {
+                pads,
+                interval_sec: self.land_interval,
+                alt_step: self.land_alt_step,
+            })
+        } else {
+            None
+        };
+        let facing = match (self.face_center, self.face_heading) {
+            (true, _) => Facing::Center,
+            (false, Some(heading)) => Facing::Heading(heading),
+            (false, None) => Facing::Free,
+        };
+        Ok(PlanOptions {
+            fence_margin: self.fence_margin,
+            shared_fence: self.shared_fence,
+            exclusions,
+            rally: self.rally,
+            airspaces: self.airspace.load()?,
+            home_elevation: self.airspace.home_elevation,
+            terrain,
+            terrain_follow: self.terrain_follow,
+            min_clearance: self.min_clearance,
+            facing,
+            acceptance_radius: self.acceptance_radius,
+            pass_radius: self.pass_radius,
+            profile: self.profile.to_profile()?,
+            turn_radius: self.turn_radius,
+            loiter_turns: self.loiter_turns,
+            land_heading: self.land_heading,
+            landing,
+            return_alt: self.rtl_alt,
+            return_alt_step: self.rtl_alt_step,
+            return_path: self.return_path,
+            repeat: self.repeat,
+            repeat_from: self.repeat_from,
+            waypoint_actions: self.waypoint_actions.clone(),
+            slot_actions: self.slot_actions.clone(),
+        })
+    }
+}
+
+#[derive(Debug, Args)]
+struct AirspaceArgs {
+    #[clap(long = "airspace")]
+    #[clap(help = "Path to an OpenAir airspace file to check planned paths against")]
+    openair: Option<String>,
+
+    #[clap(long = "restricted")]
+    #[clap(help = "Path to a GeoJSON file of restricted areas to check planned paths against")]
+    geojson: Option<String>,
+
+    #[clap(long = "home-elevation", default_value = "0")]
+    #[clap(help = "Launch point elevation in meters AMSL, compared against AMSL airspace floors")]
+    home_elevation: f64,
+}
+
+impl AirspaceArgs {
+    fn load(&self) -> io::Result<Vec<Airspace>> {
+        let mut airspaces = vec![];
+        if let Some(path) = &self.openair {
+            airspaces.extend(Airspace::load_openair(Path::new(path.as_str()))?);
+        }
+        if let Some(path) = &self.geojson {
+            airspaces.extend(Airspace::load_geojson(Path::new(path.as_str()))?);
+        }
+        Ok(airspaces)
+    }
+}
+
+#[derive(Debug, Args)]
+#[clap(about = "Check plan files against airspace before a run")]
+#[clap(arg_required_else_help = true)]
+struct Validate {
+    #[clap(help = "One or more .plan files to check")]
+    #[clap(short = 'p')]
+    plans: Vec<String>,
+
+    #[clap(long = "manifest")]
+    #[clap(help = "Path to a TOML run manifest whose plans are checked")]
+    manifest: Option<String>,
+
+    #[clap(flatten)]
+    airspace: AirspaceArgs,
+
+    #[clap(long = "terrain")]
+    #[clap(help = "Path to a DEM tile or directory of tiles, for items in the terrain frame")]
+    terrain: Option<String>,
+}
+
+#[derive(Debug, Args)]
+#[clap(about = "Download the mission, geofence and rally points on a vehicle into a .plan file")]
+#[clap(arg_required_else_help = true)]
+struct Pull {
+    #[clap(help = "Uri to the vechicle's MavSDK Interface")]
+    #[clap(short = 'v')]
+    vehicle: String,
+
+    #[clap(help = "Path of the .plan file to write")]
+    #[clap(short = 'o')]
+    output: String,
+
+    #[clap(long = "timeout", default_value = "3")]
+    #[clap(help = "Seconds to wait for the vehicle to be discovered")]
+    timeout: u32,
 }
 
 #[derive(Debug, Subcommand)]
@@ -69,6 +487,9 @@ enum GenerateCommands {
     Circle(GenerateCircle),
     Square(GenerateSquare),
     Line(GenerateLine),
+    Survey(GenerateSurvey),
+    Points(GeneratePoints),
+    Inspect(GenerateInspect),
 }
 
 #[derive(Debug, Args)]
@@ -103,11 +524,57 @@ struct GenerateCircle {
     #[clap(help = "The shape's location altitude")]
     target_alt: u8,
 
-    #[clap(short = 'h')]
+    #[clap(long = "hold")]
     #[clap(help = "How long to hold the shape in seconds")]
     hold_sec: u8,
 }
 
+impl GenerateCommands {
+    // to_shape_mission builds the shape to generate, printing what is generated
+    fn to_shape_mission(&self) -> io::Result<Box<dyn ShapeMission>> {
+        let mission: Box<dyn ShapeMission> = match self {
+            GenerateCommands::Circle(circle) => {
+                println!("Generate circle shape with {} vehicles", circle.count);
+                Box::new(circle.to_circle_mission())
+            }
+            GenerateCommands::Square(square) => {
+                println!(
+                    "Generating square shape with sides length {} meters",
+                    square.width
+                );
+                Box::new(square.to_square_mission())
+            }
+            GenerateCommands::Line(line) => {
+                println!("Generate line shape at angle {} radians", line.angle);
+                Box::new(line.to_line_mission())
+            }
+            GenerateCommands::Survey(survey) => {
+                println!(
+                    "Generate survey with {} vehicles at {} meter spacing",
+                    survey.count, survey.spacing
+                );
+                let mission = survey.to_survey_mission()?;
+                mission.check_lines()?;
+                Box::new(mission)
+            }
+            GenerateCommands::Points(points) => {
+                println!("Generate point routes for {} vehicles", points.count);
+                let mission = points.to_points_mission()?;
+                mission.check_endurance()?;
+                Box::new(mission)
+            }
+            GenerateCommands::Inspect(inspect) => {
+                println!(
+                    "Generate inspection orbit with {} vehicles at radius {} meters",
+                    inspect.count, inspect.radius
+                );
+                Box::new(inspect.to_inspection_mission())
+            }
+        };
+        Ok(mission)
+    }
+}
+
 impl GenerateCircle {
     fn to_circle_mission(&self) -> CircleMission {
         CircleMission::new(
@@ -151,7 +618,7 @@ struct GenerateSquare {
     #[clap(help = "The shape's location altitude")]
     target_alt: u8,
 
-    #[clap(short = 'h')]
+    #[clap(long = "hold")]
     #[clap(help = "How long to hold the shape in seconds")]
     hold_sec: u8,
 }
@@ -202,7 +669,7 @@ struct GenerateLine {
     #[clap(help = "The shape's location altitude")]
     target_alt: u8,
 
-    #[clap(short = 'h')]
+    #[clap(long = "hold")]
     #[clap(help = "How long to hold the shape in seconds")]
     hold_sec: u8,
 }
@@ -222,6 +689,154 @@ impl GenerateLine {
     }
 }
 
+#[derive(Debug, Args)]
+#[clap(about = "Create a lawnmower survey of an area split between vehicles")]
+struct GenerateSurvey {
+    #[clap(long = "polygon")]
+    #[clap(help = "Path to a CSV (lat,lon per line) or GeoJSON file with the area's vertices")]
+    polygon: String,
+
+    #[clap(short = 'c')]
+    #[clap(help = "Number of vehicles to split the area between")]
+    count: u8,
+
+    #[clap(short = 's')]
+    #[clap(help = "Distance between survey lines in meters")]
+    spacing: f64,
+
+    #[clap(long = "heading", default_value = "0")]
+    #[clap(help = "Direction of the survey lines in degrees clockwise from north")]
+    heading: f64,
+
+    #[clap(long = "slat")]
+    #[clap(help = "The Drone's starting Latitude")]
+    start_lat: f64,
+
+    #[clap(long = "slon")]
+    #[clap(help = "The Drone's starting Longitude")]
+    start_lon: f64,
+
+    #[clap(long = "talt")]
+    #[clap(help = "The survey altitude")]
+    target_alt: u8,
+
+    #[clap(long = "trigger-distance")]
+    #[clap(help = "Trigger the camera every this many meters along the survey lines")]
+    trigger_distance: Option<f64>,
+}
+
+impl GenerateSurvey {
+    fn to_survey_mission(&self) -> io::Result<SurveyMission> {
+        Ok(SurveyMission::new(
+            points::load_points(Path::new(self.polygon.as_str()))?,
+            self.count,
+            self.spacing,
+            self.heading,
+            self.start_lat,
+            self.start_lon,
+            self.target_alt,
+            self.trigger_distance,
+        ))
+    }
+}
+
+#[derive(Debug, Args)]
+#[clap(about = "Split a list of points between vehicles, minimizing the longest route")]
+struct GeneratePoints {
+    #[clap(long = "points")]
+    #[clap(help = "Path to a CSV (lat,lon per line) or GeoJSON file with the points to visit")]
+    points: String,
+
+    #[clap(short = 'c')]
+    #[clap(help = "Number of vehicles to split the points between")]
+    count: u8,
+
+    #[clap(long = "slat")]
+    #[clap(help = "The Drone's starting Latitude")]
+    start_lat: f64,
+
+    #[clap(long = "slon")]
+    #[clap(help = "The Drone's starting Longitude")]
+    start_lon: f64,
+
+    #[clap(long = "talt")]
+    #[clap(help = "The altitude to visit the points at")]
+    target_alt: u8,
+
+    #[clap(long = "hold", default_value = "0")]
+    #[clap(help = "How long to hold at each point in seconds")]
+    hold_sec: u8,
+
+    #[clap(long = "endurance")]
+    #[clap(help = "Longest route in meters a vehicle can fly, including its return")]
+    endurance: Option<f64>,
+}
+
+impl GeneratePoints {
+    fn to_points_mission(&self) -> io::Result<PointsMission> {
+        Ok(PointsMission::new(
+            points::load_points(Path::new(self.points.as_str()))?,
+            self.count,
+            self.start_lat,
+            self.start_lon,
+            self.target_alt,
+            self.hold_sec,
+            self.endurance,
+        ))
+    }
+}
+
+#[derive(Debug, Args)]
+#[clap(about = "Orbit a point or structure at one or more altitude rings, facing it")]
+struct GenerateInspect {
+    #[clap(short = 'c')]
+    #[clap(help = "Number of vehicles spread around the orbit")]
+    count: u8,
+
+    #[clap(short = 'r')]
+    #[clap(help = "Radius of the orbit in meters")]
+    radius: u8,
+
+    #[clap(long = "ring-alt", required = true)]
+    #[clap(help = "Altitude of an orbit ring, repeat for several rings")]
+    ring_alts: Vec<u8>,
+
+    #[clap(long = "ring-points", default_value = "8")]
+    #[clap(help = "Number of waypoints around each ring")]
+    ring_points: u8,
+
+    #[clap(long = "slat")]
+    #[clap(help = "The Drone's starting Latitude")]
+    start_lat: f64,
+
+    #[clap(long = "slon")]
+    #[clap(help = "The Drone's starting Longitude")]
+    start_lon: f64,
+
+    #[clap(long = "tlat")]
+    #[clap(help = "The structure's Latitude")]
+    target_lat: f64,
+
+    #[clap(long = "tlon")]
+    #[clap(help = "The structure's Longitude")]
+    target_lon: f64,
+}
+
+impl GenerateInspect {
+    fn to_inspection_mission(&self) -> InspectionMission {
+        InspectionMission::new(
+            self.count,
+            self.radius,
+            self.ring_alts.clone(),
+            self.ring_points,
+            self.start_lat,
+            self.start_lon,
+            self.target_lat,
+            self.target_lon,
+        )
+    }
+}
+
 #[derive(Debug, Args)]
 #[clap(short_flag = 'e')]
 #[clap(long_flag = "echo")]
@@ -237,47 +852,108 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     match args.command {
         Commands::UI {} => {
-            app::run(Duration::from_millis(200), None, false);
+            app::run(Duration::from_millis(200), None, false).await?;
         }
         Commands::Run(run) => {
-            if run.vehicles.len() != run.plans.len() {
-                panic!("Error, vehicle and plan count mismatch")
-            }
-            let mut sets = vec![];
-            for i in 0..run.vehicles.len() {
-                println!("Found {} {}", run.vehicles[i], run.plans[i]);
-                sets.push((run.vehicles[i].clone(), run.plans[i].clone()));
+            let manifest = run.to_manifest()?;
+            for vehicle in &manifest.vehicles {
+                println!("Found {} {} {}", vehicle.name, vehicle.uri, vehicle.plan);
             }
-            app::run(Duration::from_millis(200), Some(sets), true);
+            app::run(Duration::from_millis(200), Some(manifest), true).await?;
         }
         Commands::Generate(generate) => {
-            let gen_cmd = generate.command;
-            match gen_cmd {
-                GenerateCommands::Circle(circle) => {
-                    println!("Generate circle shape with {} vehicles", circle.count);
-                    circle
-                        .to_circle_mission()
-                        .write_mission_to_disk(Path::new(generate.path.as_str()))?;
-                }
-                GenerateCommands::Square(square) => {
-                    println!(
-                        "Generating square shape with sides length {} meters",
-                        square.width
-                    );
-                    square
-                        .to_square_mission()
-                        .write_mission_to_disk(Path::new(generate.path.as_str()))?;
-                }
-                GenerateCommands::Line(line) => {
-                    println!("Generate line shape at angle {} radians", line.angle);
-                    line.to_line_mission()
-                        .write_mission_to_disk(Path::new(generate.path.as_str()))?;
-                }
+            let options = generate.plan.to_plan_options()?;
+            generate
+                .command
+                .to_shape_mission()?
+                .write_mission_to_disk(Path::new(generate.path.as_str()), &options)?;
+        }
+        Commands::Validate(validate) => {
+            let airspaces = validate.airspace.load()?;
+            let mut plans = validate.plans.clone();
+            if let Some(path) = &validate.manifest {
+                plans.extend(Manifest::load(Path::new(path.as_str()))?.plans());
+            }
+            let terrain = match &validate.terrain {
+                Some(path) => Some(Terrain::load(Path::new(path.as_str()))?),
+                None => None,
+            };
+            if !validate::validate_plans(
+                &plans,
+                &airspaces,
+                validate.airspace.home_elevation,
+                terrain.as_ref(),
+            )? {
+                return Err("One or more plans failed validation".into());
             }
         }
+        Commands::Pull(pull) => {
+            pull::pull_plan(&pull.vehicle, pull.timeout, Path::new(pull.output.as_str()))?;
+            println!("Pulled plan from {} into {}", pull.vehicle, pull.output);
+        }
         Commands::Echo(echo) => {
             println!("Echo: {}", echo.text)
         }
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn cli_is_valid() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn shape_args_are_valid() {
+        ShapeArgs::command().debug_assert();
+    }
+
+    #[test]
+    fn shape_takes_hold() {
+        let shape = ShapeArgs::try_parse_from([
+            "circle", "-c", "4", "-r", "10", "--slat", "47.39", "--slon", "8.54", "--tlat", "47.4",
+            "--tlon", "8.55", "--talt", "20", "--hold", "5",
+        ])
+        .unwrap();
+        match shape.command {
+            GenerateCommands::Circle(circle) => assert_eq!(circle.hold_sec, 5),
+            command => panic!("parsed {:?}", command),
+        }
+    }
+
+    #[test]
+    fn generate_points_takes_hold() {
+        let cli = Cli::try_parse_from([
+            "flightctl",
+            "generate",
+            "-p",
+            "out",
+            "points",
+            "--points",
+            "points.csv",
+            "-c",
+            "2",
+            "--slat",
+            "47.39",
+            "--slon",
+            "8.54",
+            "--talt",
+            "20",
+            "--hold",
+            "5",
+        ])
+        .unwrap();
+        match cli.command {
+            Commands::Generate(Generate {
+                command: GenerateCommands::Points(points),
+                ..
+            }) => assert_eq!(points.hold_sec, 5),
+            command => panic!("parsed {:?}", command),
+        }
+    }
+}
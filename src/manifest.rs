@@ -0,0 +1,328 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use clap::ArgEnum;
+use toml::Value;
+
+use crate::geofence::Fence;
+use crate::run_mode::{FailurePolicy, FenceAction, RunOptions, TelemetryRate};
+
+// ManifestVehicle is a single vehicle of a run and the plan it flies
+#[derive(Clone, Debug)]
+pub struct ManifestVehicle {
+    pub name: String,
+    // MAVSDK connection uri such as udp://:14540
+    pub uri: String,
+    pub plan: String,
+    // plan generated in memory for run --shape, flown instead of reading plan from disk
+    pub mission: Option<String>,
+    // free form role shown beside the name, such as leader or spare
+    pub role: String,
+    // seconds after the mission start before this vehicle starts
+    pub launch_delay: f64,
+}
+
+// Manifest describes a run, the vehicles flown and the policy shared by all of them
+//
+// [run]
+// timeout = 10
+// t_zero = 1767225600
+// failure = "rtl"
+// fence = "fence.plan"
+// fence_action = "hold"
+// rtl_alt = 40
+// rtl_alt_step = 5
+// min_battery = 60
+//
+// [run.rates]
+// battery = 2
+// velocity = 5
+//
+// [[vehicle]]
+// name = "alpha"
+// uri = "udp://:14540"
+// plan = "plans/plan_0.plan"
+// role = "leader"
+// launch_delay = 0
+#[derive(Clone)]
+pub struct Manifest {
+    pub vehicles: Vec<ManifestVehicle>,
+    pub options: RunOptions,
+}
+
+impl Manifest {
+    // from_lists pairs vehicle uris with plans by position, as given with -v and -p
+    pub fn from_lists(
+        uris: &[String],
+        plans: &[String],
+        options: RunOptions,
+    ) -> io::Result<Manifest> {
+        if uris.len() != plans.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} vehicles given for {} plans", uris.len(), plans.len()),
+            ));
+        }
+        let vehicles = uris
+            .iter()
+            .zip(plans.iter())
+            .enumerate()
+            .map(|(i, (uri, plan))| ManifestVehicle {
+                name: format!("Vehicle {}", i),
+                uri: uri.clone(),
+                plan: plan.clone(),
+                mission: None,
+                role: String::new(),
+                launch_delay: 0.0,
+            })
+            .collect();
+        Ok(Manifest { vehicles, options })
+    }
+
+    // from_generated pairs vehicle uris with plans generated in memory by position
+    pub fn from_generated(
+        uris: &[String],
+        plans: Vec<String>,
+        options: RunOptions,
+    ) -> io::Result<Manifest> {
+        if uris.len() != plans.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} vehicles given for {} plans", uris.len(), plans.len()),
+            ));
+        }
+        let vehicles = uris
+            .iter()
+            .zip(plans)
+            .enumerate()
+            .map(|(i, (uri, mission))| ManifestVehicle {
+                name: format!("Vehicle {}", i),
+                uri: uri.clone(),
+                plan: format!("generated plan {}", i),
+                mission: Some(mission),
+                role: String::new(),
+                launch_delay: 0.0,
+            })
+            .collect();
+        Ok(Manifest { vehicles, options })
+    }
+
+    // load reads a TOML manifest, plan and fence paths are relative to the manifest
+    pub fn load(path: &Path) -> io::Result<Manifest> {
+        let invalid = |msg: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), msg),
+            )
+        };
+        let data = fs::read_to_string(path)?;
+        let value: Value = data
+            .parse()
+            .map_err(|e: toml::de::Error| invalid(e.to_string()))?;
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let relative = |file: &str| -> String {
+            let file = PathBuf::from(file);
+            if file.is_absolute() {
+                file.display().to_string()
+            } else {
+                dir.join(file).display().to_string()
+            }
+        };
+
+        let run = value
+            .get("run")
+            .cloned()
+            .unwrap_or(Value::Table(Default::default()));
+        let mut options = RunOptions::default();
+        if let Some(fence) = run.get("fence") {
+            let fence = fence
+                .as_str()
+                .ok_or_else(|| invalid("run.fence must be a path".to_string()))?;
+            options.fence = Some(Fence::load(Path::new(relative(fence).as_str()))?);
+        }
+        if let Some(action) = run.get("fence_action") {
+            options.fence_action = action
+                .as_str()
+                .and_then(|action| FenceAction::from_str(action, true).ok())
+                .ok_or_else(|| {
+                    invalid("run.fence_action must be report, hold or rtl".to_string())
+                })?;
+        }
+        if let Some(policy) = run.get("failure") {
+            options.failure_policy = policy
+                .as_str()
+                .and_then(|policy| FailurePolicy::from_str(policy, true).ok())
+                .ok_or_else(|| {
+                    invalid("run.failure must be continue, hold, rtl or close-up".to_string())
+                })?;
+        }
+        if let Some(timeout) = run.get("timeout") {
+            options.timeout = timeout
+                .as_integer()
+                .filter(|timeout| *timeout > 0)
+                .ok_or_else(|| invalid("run.timeout must be a number of seconds".to_string()))?
+                as u32;
+        }
+        if let Some(t_zero) = run.get("t_zero") {
+            options.t_zero = Some(
+                t_zero
+                    .as_integer()
+                    .filter(|t_zero| *t_zero > 0)
+                    .ok_or_else(|| {
+                        invalid("run.t_zero must be a unix time in seconds".to_string())
+                    })? as u64,
+            );
+        }
+        if let Some(alt) = run.get("rtl_alt") {
+            options.return_alt = Some(
+                number(alt).ok_or_else(|| invalid("run.rtl_alt must be a number".to_string()))?
+                    as f32,
+            );
+        }
+        if let Some(step) = run.get("rtl_alt_step") {
+            options.return_alt_step = number(step)
+                .ok_or_else(|| invalid("run.rtl_alt_step must be a number".to_string()))?
+                as f32;
+        }
+        if let Some(battery) = run.get("min_battery") {
+            options.min_battery = number(battery)
+                .ok_or_else(|| invalid("run.min_battery must be a percentage".to_string()))?
+                as f32;
+        }
+        if let Some(rates) = run.get("rates") {
+            let rates = rates
+                .as_table()
+                .ok_or_else(|| invalid("run.rates must be a table of rates".to_string()))?;
+            for (stream, hz) in rates {
+                let hz = number(hz)
+                    .ok_or_else(|| invalid(format!("run.rates.{} must be a number", stream)))?;
+                let rate: TelemetryRate = format!("{}={}", stream, hz).parse().map_err(invalid)?;
+                rate.apply(&mut options.telemetry_rates);
+            }
+        }
+
+        let mut vehicles = vec![];
+        let entries = value
+            .get("vehicle")
+            .and_then(|v| v.as_array())
+            .cloned()
+            .unwrap_or_default();
+        for (i, entry) in entries.iter().enumerate() {
+            let text = |key: &str| entry.get(key).and_then(|v| v.as_str());
+            let uri = text("uri").ok_or_else(|| invalid(format!("vehicle {} has no uri", i)))?;
+            let plan = text("plan").ok_or_else(|| invalid(format!("vehicle {} has no plan", i)))?;
+            vehicles.push(ManifestVehicle {
+                name: text("name")
+                    .map(|name| name.to_string())
+                    .unwrap_or_else(|| format!("Vehicle {}", i)),
+                uri: uri.to_string(),
+                plan: relative(plan),
+                mission: None,
+                role: text("role").unwrap_or("").to_string(),
+                launch_delay: entry.get("launch_delay").and_then(number).unwrap_or(0.0),
+            });
+        }
+        if vehicles.is_empty() {
+            return Err(invalid("No [[vehicle]] entries".to_string()));
+        }
+        Ok(Manifest { vehicles, options })
+    }
+
+    pub fn plans(&self) -> Vec<String> {
+        self.vehicles.iter().map(|v| v.plan.clone()).collect()
+    }
+}
+
+impl ManifestVehicle {
+    // load_plan parses the vehicle's QGC plan, generated or read from disk
+    pub fn load_plan(&self) -> io::Result<serde_json::Value> {
+        let data = match &self.mission {
+            Some(mission) => mission.clone(),
+            None => fs::read_to_string(&self.plan)?,
+        };
+        let plan: serde_json::Value = serde_json::from_str(data.as_str())?;
+        Ok(plan)
+    }
+}
+
+// number reads a TOML integer or float
+fn number(value: &Value) -> Option<f64> {
+    value
+        .as_float()
+        .or_else(|| value.as_integer().map(|v| v as f64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(name: &str, text: &str) -> io::Result<Manifest> {
+        let dir = std::env::temp_dir().join(format!("flightctl-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, text).unwrap();
+        let manifest = Manifest::load(&path);
+        fs::remove_file(&path).unwrap();
+        manifest
+    }
+
+    #[test]
+    fn load_reads_run_options_and_vehicles() {
+        let manifest = load(
+            "run.toml",
+            "[run]\n\
+             timeout = 10\n\
+             failure = \"close-up\"\n\
+             fence_action = \"hold\"\n\
+             rtl_alt = 40\n\
+             min_battery = 60.5\n\
+             \n\
+             [[vehicle]]\n\
+             name = \"alpha\"\n\
+             uri = \"udp://:14540\"\n\
+             plan = \"plans/alpha.plan\"\n\
+             role = \"leader\"\n\
+             launch_delay = 2.5\n\
+             \n\
+             [[vehicle]]\n\
+             uri = \"udp://:14541\"\n\
+             plan = \"/abs/bravo.plan\"\n",
+        )
+        .unwrap();
+        let options = &manifest.options;
+        assert_eq!(options.timeout, 10);
+        assert!(options.failure_policy == FailurePolicy::CloseUp);
+        assert!(options.fence_action == FenceAction::Hold);
+        assert_eq!(options.return_alt, Some(40.0));
+        assert_eq!(options.min_battery, 60.5);
+
+        let alpha = &manifest.vehicles[0];
+        assert_eq!(alpha.name, "alpha");
+        assert_eq!(alpha.role, "leader");
+        assert_eq!(alpha.launch_delay, 2.5);
+        // plan paths are relative to the manifest, absolute paths are kept
+        assert!(Path::new(&alpha.plan).starts_with(std::env::temp_dir()));
+        assert!(Path::new(&alpha.plan).ends_with("plans/alpha.plan"));
+        let bravo = &manifest.vehicles[1];
+        assert_eq!(bravo.name, "Vehicle 1");
+        assert_eq!(bravo.plan, "/abs/bravo.plan");
+    }
+
+    #[test]
+    fn load_rejects_invalid_manifests() {
+        assert!(load("empty.toml", "[run]\ntimeout = 5\n").is_err());
+        assert!(load("no-uri.toml", "[[vehicle]]\nplan = \"a.plan\"\n").is_err());
+        let policy = "[run]\nfailure = \"panic\"\n[[vehicle]]\nuri = \"u\"\nplan = \"a.plan\"\n";
+        assert!(load("policy.toml", policy).is_err());
+    }
+
+    #[test]
+    fn from_lists_pairs_uris_and_plans() {
+        let uris = vec![String::from("udp://:14540")];
+        let plans = vec![String::from("a.plan"), String::from("b.plan")];
+        assert!(Manifest::from_lists(&uris, &plans, RunOptions::default()).is_err());
+        let manifest = Manifest::from_lists(&uris, &plans[..1], RunOptions::default()).unwrap();
+        assert_eq!(manifest.plans(), vec![String::from("a.plan")]);
+    }
+}
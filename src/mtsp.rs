@@ -0,0 +1,246 @@
+// Min-max multiple traveling salesman heuristic
+//
+// Every route starts and ends at the shared start point. Points are first assigned by cheapest
+// insertion into whichever route stays shortest, then routes are improved with 2-opt and by
+// relocating or swapping points off the longest route, and off every route longer than the
+// endurance, until neither the distance flown over the endurance nor the longest route gets
+// any shorter.
+
+type Xy = (f64, f64);
+
+// Move takes point i off route a and inserts it into route b at position, swapping when set
+// with point j of route b inserted into route a at its own position
+struct Move {
+    a: usize,
+    i: usize,
+    b: usize,
+    position: usize,
+    swap: Option<(usize, usize)>,
+}
+
+// score ranks a split by the meters flown over the endurance, then by the longest route
+fn score(lengths: &[f64], endurance: f64) -> (f64, f64) {
+    let excess = lengths.iter().map(|l| (l - endurance).max(0.0)).sum();
+    let longest = lengths.iter().copied().fold(0.0, f64::max);
+    (excess, longest)
+}
+
+fn better(a: (f64, f64), b: (f64, f64)) -> bool {
+    a.0 + 1e-9 < b.0 || (a.0 <= b.0 + 1e-9 && a.1 + 1e-9 < b.1)
+}
+
+fn distance(a: Xy, b: Xy) -> f64 {
+    (a.0 - b.0).hypot(a.1 - b.1)
+}
+
+// route_length is the length of start -> route points -> start
+pub fn route_length(start: Xy, points: &[Xy], route: &[usize]) -> f64 {
+    let mut length = 0.0;
+    let mut last = start;
+    for &i in route {
+        length += distance(last, points[i]);
+        last = points[i];
+    }
+    length + distance(last, start)
+}
+
+// insertion_cost returns the cheapest added length and position to insert point into route
+fn insertion_cost(start: Xy, points: &[Xy], route: &[usize], point: usize) -> (f64, usize) {
+    let mut best = (f64::INFINITY, 0);
+    for position in 0..=route.len() {
+        let before = if position == 0 {
+            start
+        } else {
+            points[route[position - 1]]
+        };
+        let after = if position == route.len() {
+            start
+        } else {
+            points[route[position]]
+        };
+        let cost = distance(before, points[point]) + distance(points[point], after)
+            - distance(before, after);
+        if cost < best.0 {
+            best = (cost, position);
+        }
+    }
+    best
+}
+
+// two_opt reverses segments of the route while it makes the route shorter
+fn two_opt(start: Xy, points: &[Xy], route: &mut [usize]) {
+    let mut improved = true;
+    while improved {
+        improved = false;
+        let length = route_length(start, points, route);
+        for i in 0..route.len() {
+            for j in (i + 1)..route.len() {
+                route[i..=j].reverse();
+                if route_length(start, points, route) + 1e-9 < length {
+                    improved = true;
+                    break;
+                }
+                route[i..=j].reverse();
+            }
+            if improved {
+                break;
+            }
+        }
+    }
+}
+
+// distribute splits points between vehicles, keeping routes within the endurance in meters and
+// minimizing the longest route, returns the ordered point indices flown by each vehicle
+pub fn distribute(
+    start: Xy,
+    points: &[Xy],
+    vehicles: usize,
+    endurance: Option<f64>,
+) -> Vec<Vec<usize>> {
+    let mut routes: Vec<Vec<usize>> = vec![vec![]; vehicles];
+    if vehicles == 0 {
+        return routes;
+    }
+
+    // assign the points furthest from the start first so they seed separate routes
+    let mut order: Vec<usize> = (0..points.len()).collect();
+    order.sort_by(|a, b| distance(start, points[*b]).total_cmp(&distance(start, points[*a])));
+    for point in order {
+        let mut best = (f64::INFINITY, 0, 0);
+        for (v, route) in routes.iter().enumerate() {
+            let (cost, position) = insertion_cost(start, points, route, point);
+            let length = route_length(start, points, route) + cost;
+            if length < best.0 {
+                best = (length, v, position);
+            }
+        }
+        routes[best.1].insert(best.2, point);
+    }
+    for route in routes.iter_mut() {
+        two_opt(start, points, route);
+    }
+
+    // move points off the longest route and the routes over the endurance while it helps
+    let endurance = endurance.unwrap_or(f64::INFINITY);
+    loop {
+        let lengths: Vec<f64> = routes
+            .iter()
+            .map(|route| route_length(start, points, route))
+            .collect();
+        let longest = (0..vehicles)
+            .max_by(|a, b| lengths[*a].total_cmp(&lengths[*b]))
+            .unwrap();
+        let current = score(&lengths, endurance);
+        let mut best: Option<((f64, f64), Move)> = None;
+        let mut consider = |lengths: Vec<f64>, candidate: Move| {
+            let candidate_score = score(&lengths, endurance);
+            if better(candidate_score, best.as_ref().map_or(current, |b| b.0)) {
+                best = Some((candidate_score, candidate));
+            }
+        };
+        let sources = (0..vehicles).filter(|v| *v == longest || lengths[*v] > endurance);
+        for a in sources {
+            for i in 0..routes[a].len() {
+                let mut shortened = routes[a].clone();
+                let point = shortened.remove(i);
+                let shortened_length = route_length(start, points, &shortened);
+                for b in (0..vehicles).filter(|b| *b != a) {
+                    let (cost, position) = insertion_cost(start, points, &routes[b], point);
+                    let mut relocated = lengths.clone();
+                    relocated[a] = shortened_length;
+                    relocated[b] = lengths[b] + cost;
+                    consider(
+                        relocated,
+                        Move {
+                            a,
+                            i,
+                            b,
+                            position,
+                            swap: None,
+                        },
+                    );
+                    for j in 0..routes[b].len() {
+                        let mut other = routes[b].clone();
+                        let swapped = other.remove(j);
+                        let (cost_b, position_b) = insertion_cost(start, points, &other, point);
+                        let (cost_a, position_a) =
+                            insertion_cost(start, points, &shortened, swapped);
+                        let mut exchanged = lengths.clone();
+                        exchanged[a] = shortened_length + cost_a;
+                        exchanged[b] = route_length(start, points, &other) + cost_b;
+                        let swap = Some((j, position_a));
+                        consider(
+                            exchanged,
+                            Move {
+                                a,
+                                i,
+                                b,
+                                position: position_b,
+                                swap,
+                            },
+                        );
+                    }
+                }
+            }
+        }
+        let chosen = match best {
+            Some((_, chosen)) => chosen,
+            None => break,
+        };
+        let point = routes[chosen.a].remove(chosen.i);
+        if let Some((j, position_a)) = chosen.swap {
+            let swapped = routes[chosen.b].remove(j);
+            routes[chosen.a].insert(position_a, swapped);
+        }
+        routes[chosen.b].insert(chosen.position, point);
+        two_opt(start, points, &mut routes[chosen.a]);
+        two_opt(start, points, &mut routes[chosen.b]);
+    }
+    routes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(routes: &[Vec<usize>]) -> Vec<usize> {
+        let mut all: Vec<usize> = routes.concat();
+        all.sort();
+        all
+    }
+
+    #[test]
+    fn distribute_visits_every_point_once() {
+        let points: Vec<Xy> = (0..12)
+            .map(|i| ((i % 4) as f64 * 100.0, (i / 4) as f64 * 100.0 + 50.0))
+            .collect();
+        let routes = distribute((0.0, 0.0), &points, 3, None);
+        assert_eq!(routes.len(), 3);
+        assert_eq!(sorted(&routes), (0..12).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn distribute_splits_opposite_clusters() {
+        let points = [(1000.0, 0.0), (1010.0, 0.0), (-1000.0, 0.0), (-1010.0, 0.0)];
+        let routes = distribute((0.0, 0.0), &points, 2, None);
+        for route in &routes {
+            assert_eq!(route.len(), 2);
+            let east = points[route[0]].0 > 0.0;
+            assert!(route.iter().all(|&i| (points[i].0 > 0.0) == east));
+        }
+    }
+
+    #[test]
+    fn distribute_keeps_routes_within_endurance() {
+        let points = [(100.0, 0.0), (200.0, 0.0), (300.0, 0.0), (400.0, 0.0)];
+        let routes = distribute((0.0, 0.0), &points, 2, Some(800.0));
+        for route in &routes {
+            assert!(route_length((0.0, 0.0), &points, route) <= 800.0);
+        }
+    }
+
+    #[test]
+    fn distribute_without_vehicles_returns_no_routes() {
+        assert!(distribute((0.0, 0.0), &[(1.0, 1.0)], 0, None).is_empty());
+    }
+}
@@ -0,0 +1,80 @@
+use serde_json::Value;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// load_points reads (lat, lon) points from a GeoJSON file of Point, MultiPoint, LineString or
+// Polygon features, or from a CSV file with a lat,lon pair on each line
+pub fn load_points(path: &Path) -> io::Result<Vec<(f64, f64)>> {
+    let data = fs::read_to_string(path)?;
+    let is_geojson = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.eq_ignore_ascii_case("geojson") || e.eq_ignore_ascii_case("json"))
+        .unwrap_or(false);
+    let points = if is_geojson {
+        let value: Value = serde_json::from_str(data.as_str())?;
+        read_geojson(&value)
+    } else {
+        read_csv(data.as_str())
+    };
+    if points.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("No points found in {}", path.display()),
+        ));
+    }
+    Ok(points)
+}
+
+fn read_csv(data: &str) -> Vec<(f64, f64)> {
+    let mut points = vec![];
+    for line in data.lines() {
+        let mut fields = line.split(',').map(|field| field.trim().parse::<f64>());
+        // lines that do not start with two numbers, such as a header, are skipped
+        if let (Some(Ok(lat)), Some(Ok(lon))) = (fields.next(), fields.next()) {
+            points.push((lat, lon));
+        }
+    }
+    points
+}
+
+fn read_geojson(value: &Value) -> Vec<(f64, f64)> {
+    let mut points = vec![];
+    let geometries: Vec<&Value> = match value["type"].as_str() {
+        Some("FeatureCollection") => value["features"]
+            .as_array()
+            .map(|features| features.iter().map(|f| &f["geometry"]).collect())
+            .unwrap_or_default(),
+        Some("Feature") => vec![&value["geometry"]],
+        _ => vec![value],
+    };
+    for geometry in geometries {
+        let positions = match geometry["type"].as_str() {
+            Some("Point") => vec![&geometry["coordinates"]],
+            Some("MultiPoint") | Some("LineString") => geometry["coordinates"]
+                .as_array()
+                .map(|c| c.iter().collect())
+                .unwrap_or_default(),
+            // only the outer ring of a polygon, without the closing position
+            Some("Polygon") => {
+                let mut ring: Vec<&Value> = geometry["coordinates"][0]
+                    .as_array()
+                    .map(|c| c.iter().collect())
+                    .unwrap_or_default();
+                if ring.len() > 1 && ring.first() == ring.last() {
+                    ring.pop();
+                }
+                ring
+            }
+            _ => vec![],
+        };
+        for position in positions {
+            // GeoJSON positions are [lon, lat]
+            if let (Some(lon), Some(lat)) = (position[0].as_f64(), position[1].as_f64()) {
+                points.push((lat, lon));
+            }
+        }
+    }
+    points
+}
@@ -0,0 +1,178 @@
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::generate::{new_pulled_plan, new_simple_item};
+use crate::geofence::{Fence, FenceCircle, FencePolygon};
+use mavsdk::{PulledVehicle, RawItem};
+
+// MAV_MISSION_TYPE of the pulled items
+const MISSION_TYPE_MISSION: i32 = 0;
+const MISSION_TYPE_FENCE: i32 = 1;
+const MISSION_TYPE_RALLY: i32 = 2;
+const AUTOPILOT_ARDUPILOT: i32 = 3;
+const CMD_DO_JUMP: u32 = 177;
+const CMD_FENCE_POLYGON_INCLUSION: u32 = 5001;
+const CMD_FENCE_POLYGON_EXCLUSION: u32 = 5002;
+const CMD_FENCE_CIRCLE_INCLUSION: u32 = 5003;
+const CMD_FENCE_CIRCLE_EXCLUSION: u32 = 5004;
+const CMD_RALLY_POINT: u32 = 5100;
+// MAV_FRAME values whose x and y are latitude and longitude * 1e7
+const GLOBAL_FRAMES: [u32; 6] = [0, 3, 5, 6, 10, 11];
+// MAV_FRAME_MISSION, x and y are params 5 and 6 as they are
+const FRAME_MISSION: u32 = 2;
+
+// pull_plan connects to the vehicle at uri and writes its mission, geofence and rally points
+// to a QGC plan at output
+pub fn pull_plan(uri: &str, timeout: u32, output: &Path) -> io::Result<()> {
+    let failed = |msg: &str| io::Error::other(format!("{}: {}", uri, msg));
+    let addr = CString::new(uri).map_err(|_| failed("Invalid uri"))?;
+    let sdk = unsafe { mavsdk::new_mavsdk() };
+    let system = unsafe { mavsdk::connect(sdk, addr.as_ptr(), timeout as i32) };
+    if system.is_null() {
+        unsafe { mavsdk::del_mavsdk(sdk) };
+        return Err(failed("Failed to connect"));
+    }
+    let mut items: Vec<RawItem> = vec![];
+    let mut vehicle = PulledVehicle::default();
+    let context = &mut items as *mut Vec<RawItem> as *mut libc::c_void;
+    let result =
+        unsafe { mavsdk::pull_plan(system, context, mavsdk::pull_callback_item, &mut vehicle) };
+    unsafe { mavsdk::del_mavsdk(sdk) };
+    if result != 0 {
+        return Err(failed("Failed to download the plan"));
+    }
+    fs::write(output, to_plan(&vehicle, &items).to_string())
+}
+
+// to_plan builds the plan from the pulled items, undoing what run mode changes on upload
+fn to_plan(vehicle: &PulledVehicle, items: &[RawItem]) -> Value {
+    let ardupilot = vehicle.autopilot == AUTOPILOT_ARDUPILOT;
+    let of_type = |mission_type: i32| -> Vec<RawItem> {
+        items
+            .iter()
+            .filter(|item| item.mission_type == mission_type)
+            .copied()
+            .collect()
+    };
+
+    let mut mission = of_type(MISSION_TYPE_MISSION);
+    // ArduPilot holds home as item 0, the plan keeps it as the planned home instead
+    if ardupilot && !mission.is_empty() {
+        mission.remove(0);
+    }
+    // each pulled item gets the doJumpId of its position in the plan, counting from 1
+    let jump_id = |index: usize| index as u16 + 1;
+    let mission_items = mission
+        .iter()
+        .enumerate()
+        .map(|(i, item)| {
+            let (lat, lon, alt) = position(item);
+            let mut params = [
+                item.param1 as f64,
+                item.param2 as f64,
+                item.param3 as f64,
+                item.param4 as f64,
+                lat,
+                lon,
+                alt,
+            ];
+            // DO_JUMP targets the item index on the vehicle, counted past the home on
+            // ArduPilot, the plan targets the doJumpId of that item
+            if item.command == CMD_DO_JUMP {
+                let index = if ardupilot {
+                    params[0] - 1.0
+                } else {
+                    params[0]
+                };
+                params[0] = jump_id(index.max(0.0) as usize) as f64;
+            }
+            new_simple_item(
+                item.command,
+                item.frame,
+                params,
+                item.autocontinue,
+                jump_id(i),
+            )
+        })
+        .collect();
+
+    let rally: Vec<(f64, f64, f64)> = of_type(MISSION_TYPE_RALLY)
+        .iter()
+        .filter(|item| item.command == CMD_RALLY_POINT)
+        .map(position)
+        .collect();
+    let home = if vehicle.home_alt.is_nan() {
+        (0.0, 0.0, 0.0)
+    } else {
+        (vehicle.home_lat, vehicle.home_lon, vehicle.home_alt as f64)
+    };
+    new_pulled_plan(
+        vehicle.autopilot,
+        vehicle.vehicle_type,
+        home,
+        mission_items,
+        &to_fence(&of_type(MISSION_TYPE_FENCE)),
+        &rally,
+    )
+}
+
+// to_fence reads the fence items, each polygon is a run of vertices whose first param is the
+// vertex count, the breach return point has no place in the plan and is dropped
+fn to_fence(items: &[RawItem]) -> Fence {
+    let mut fence = Fence::default();
+    let mut i = 0;
+    while i < items.len() {
+        let item = &items[i];
+        match item.command {
+            CMD_FENCE_POLYGON_INCLUSION | CMD_FENCE_POLYGON_EXCLUSION => {
+                let count = (item.param1 as usize).max(1);
+                let end = (i + count).min(items.len());
+                fence.polygons.push(FencePolygon {
+                    inclusion: item.command == CMD_FENCE_POLYGON_INCLUSION,
+                    vertices: items[i..end]
+                        .iter()
+                        .map(|vertex| {
+                            let (lat, lon, _) = position(vertex);
+                            (lat, lon)
+                        })
+                        .collect(),
+                });
+                i = end;
+                continue;
+            }
+            CMD_FENCE_CIRCLE_INCLUSION | CMD_FENCE_CIRCLE_EXCLUSION => {
+                let (lat, lon, _) = position(item);
+                fence.circles.push(FenceCircle {
+                    inclusion: item.command == CMD_FENCE_CIRCLE_INCLUSION,
+                    center: (lat, lon),
+                    radius: item.param1 as f64,
+                });
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    fence
+}
+
+// position_scale is the factor params 5 and 6 of an item in frame are scaled by to fit x and y,
+// the local frames hold meters * 1e4
+pub fn position_scale(frame: u32) -> f64 {
+    if GLOBAL_FRAMES.contains(&frame) {
+        1e7
+    } else if frame == FRAME_MISSION {
+        1.0
+    } else {
+        1e4
+    }
+}
+
+// position reads the (lat, lon, alt) of an item, x and y are scaled integers
+fn position(item: &RawItem) -> (f64, f64, f64) {
+    let scale = position_scale(item.frame);
+    (item.x as f64 / scale, item.y as f64 / scale, item.z as f64)
+}
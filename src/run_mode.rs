@@ -1,10 +1,12 @@
+use std::collections::HashMap;
 use std::ffi::CString;
-use std::future::Future;
 use std::io;
-use std::path::Path;
-use std::sync::{Arc, RwLock};
-use std::time::{Duration, Instant};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+use clap::ArgEnum;
 use crossterm::event;
 use crossterm::event::{Event, KeyCode};
 use futures::future::{join_all, JoinAll};
@@ -12,47 +14,129 @@ use tokio::task::JoinHandle;
 use tui::backend::Backend;
 use tui::layout::{Constraint, Direction, Layout, Rect};
 use tui::style::{Color, Style};
+use tui::symbols;
 use tui::text::{Span, Spans};
-use tui::widgets::{Block, Borders, LineGauge, List, ListItem, Tabs};
+use tui::widgets::{
+    Block, Borders, Cell, Clear, LineGauge, List, ListItem, Paragraph, Row, Table, Tabs, Wrap,
+};
 use tui::Frame;
 use tui::Terminal;
 
 use crate::app::TabState;
+use crate::geofence::Fence;
+use crate::manifest::Manifest;
+use crate::pull::position_scale;
+use mavsdk::{HealthReport, RawItem, RunCallBackObject, TelemetryRates, TelemetryReport};
+use serde_json::Value;
 
-fn draw<B: Backend>(f: &mut Frame<'_, B>, run_app: &RunApp<'_>) {
+fn draw<B: Backend>(f: &mut Frame<'_, B>, run_app: &RunApp) {
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .margin(1)
         .constraints([Constraint::Length(3), Constraint::Min(0)].as_ref())
         .split(f.size());
-    let run_titles = run_app
-        .tabs
-        .titles
+    let (titles, index, selected, pending, jump_input) = {
+        let input = run_app.input.lock().unwrap();
+        (
+            input.tabs.titles.clone(),
+            input.tabs.index,
+            input.selected,
+            input.pending,
+            input.jump_input.clone(),
+        )
+    };
+    let run_titles = titles
         .iter()
         .map(|t| Spans::from(Span::styled(*t, Style::default().fg(Color::Blue))))
         .collect();
     let run_tabs = Tabs::new(run_titles)
-        .block(Block::default().title("Tabs").borders(Borders::ALL))
+        .block(
+            Block::default()
+                .title(run_app.selection_title(selected))
+                .borders(Borders::ALL),
+        )
         .highlight_style(Style::default().fg(Color::Red))
-        .select(0);
+        .select(index);
     f.render_widget(run_tabs, chunks[0]);
-    draw_overview(f, run_app, chunks[1]);
+    match index {
+        1 => {
+            let chunks = Layout::default()
+                .constraints([Constraint::Percentage(70), Constraint::Percentage(30)].as_ref())
+                .split(chunks[1]);
+            draw_preflight(f, run_app, chunks[0]);
+            draw_preflight_mismatches(f, run_app, chunks[1]);
+        }
+        _ => draw_overview(f, run_app, selected, chunks[1]),
+    }
+    if let Some((target, command)) = pending {
+        let text = Spans::from(vec![
+            Span::styled(
+                format!("{:?} {}? ", command, run_app.target_name(target)),
+                Style::default().fg(Color::Red),
+            ),
+            Span::raw("Press y to confirm, any other key to cancel"),
+        ]);
+        draw_dialog(f, "Confirm", text);
+    } else if let Some(item) = jump_input {
+        let text = Spans::from(vec![
+            Span::styled(
+                format!(
+                    "Jump {} to mission item: {}_ ",
+                    run_app.target_name(selected),
+                    item
+                ),
+                Style::default().fg(Color::Yellow),
+            ),
+            Span::raw("Items count from 0 as in the plan, enter to jump, esc to cancel"),
+        ]);
+        draw_dialog(f, "Jump", text);
+    }
+}
+
+// draw_dialog draws a dialog over the middle of the screen
+fn draw_dialog<B: Backend>(f: &mut Frame<B>, title: &str, text: Spans) {
+    let size = f.size();
+    let area = Rect::new(size.width / 4, size.height / 3, size.width / 2, 5).intersection(size);
+    let dialog = Paragraph::new(text)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .wrap(Wrap { trim: true });
+    f.render_widget(Clear, area);
+    f.render_widget(dialog, area);
 }
 
-fn draw_overview<B: Backend>(f: &mut Frame<'_, B>, run_app: &RunApp<'_>, area: Rect) {
+fn draw_overview<B: Backend>(
+    f: &mut Frame<'_, B>,
+    run_app: &RunApp,
+    selected: Option<usize>,
+    area: Rect,
+) {
     let chunks = Layout::default()
         .constraints([Constraint::Percentage(80), Constraint::Percentage(20)].as_ref())
         .split(area);
-    draw_overview_gauges(f, run_app, chunks[0]);
-    draw_overview_logs(f, run_app, chunks[1]);
+    draw_overview_gauges(f, run_app, selected, chunks[0]);
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(60), Constraint::Percentage(40)].as_ref())
+        .split(chunks[1]);
+    draw_overview_logs(f, run_app, chunks[0]);
+    draw_overview_events(f, run_app, chunks[1]);
 }
 
-fn draw_overview_gauges<B: Backend>(f: &mut Frame<'_, B>, run_app: &RunApp<'_>, area: Rect) {
+fn draw_overview_gauges<B: Backend>(
+    f: &mut Frame<'_, B>,
+    run_app: &RunApp,
+    selected: Option<usize>,
+    area: Rect,
+) {
     let mut constraint = Vec::new();
     let data: Vec<f64>;
+    let names: Vec<String>;
+    let telemetry: Vec<VehicleTelemetry>;
     {
         let state = run_app.state.read().unwrap();
         data = state.progress.clone();
+        names = state.vehicles.clone();
+        telemetry = state.telemetry.clone();
     }
     let max = data.len();
     for _ in 0..max {
@@ -61,25 +145,37 @@ fn draw_overview_gauges<B: Backend>(f: &mut Frame<'_, B>, run_app: &RunApp<'_>,
 
     let chunks = Layout::default().constraints(constraint).split(area);
     let mut vehicles = Vec::new();
-    for i in 0..=8 {
-        vehicles.push(format!("Vehicle {} progress:", i));
+    for (name, telemetry) in names.iter().zip(telemetry.iter()) {
+        vehicles.push(format!("{} {} progress:", name, telemetry.summary()));
     }
 
-    let mut i = 0;
-
-    for ratio in data {
-        let label = format!("{:.2}%", ratio * 100.0);
+    for (i, ratio) in data.into_iter().enumerate() {
+        // the latest status text from the autopilot follows the progress
+        let label = match telemetry[i].status_texts.last() {
+            Some(text) => format!("{:.2}% {}", ratio * 100.0, text),
+            None => format!("{:.2}%", ratio * 100.0),
+        };
+        let border = if selected.map(|selected| selected == i).unwrap_or(true) {
+            Color::Yellow
+        } else {
+            Color::White
+        };
         let gauge = LineGauge::default()
             .block(
                 Block::default()
                     .borders(Borders::ALL)
+                    .border_style(Style::default().fg(border))
                     .title(vehicles[i].as_ref()),
             )
             .gauge_style(Style::default().fg(Color::Magenta))
+            .line_set(if run_app.enhanced_graphics {
+                symbols::line::THICK
+            } else {
+                symbols::line::NORMAL
+            })
             .label(label)
             .ratio(ratio);
         f.render_widget(gauge, chunks[i]);
-        i += 1;
     }
 }
 
@@ -103,87 +199,1276 @@ fn draw_overview_logs<B: Backend>(f: &mut Frame<B>, run_app: &RunApp, area: Rect
     f.render_widget(logs, area)
 }
 
-// RunApp handles the state of the data in run mode
-pub struct RunApp<'a> {
-    pub title: &'a str,
-    pub should_quit: bool,
-    pub tabs: TabState<'a>,
-    pub state: RwLock<RunAppState>,
+fn draw_overview_events<B: Backend>(f: &mut Frame<B>, run_app: &RunApp, area: Rect) {
+    let state = run_app.state.read().unwrap();
+    let events: Vec<ListItem> = state
+        .events
+        .iter()
+        .map(|(id, event)| {
+            let content = vec![Spans::from(vec![
+                Span::styled(
+                    format!("[{:<2}]EVENT ", id),
+                    Style::default().fg(Color::Red),
+                ),
+                Span::raw(event),
+            ])];
+            ListItem::new(content)
+        })
+        .collect();
+    let events = List::new(events).block(Block::default().borders(Borders::ALL).title("Events"));
+    f.render_widget(events, area)
+}
+
+// draw_preflight draws the preflight checklist, a row of checks for every vehicle
+fn draw_preflight<B: Backend>(f: &mut Frame<B>, run_app: &RunApp, area: Rect) {
+    let state = run_app.state.read().unwrap();
+    let header = Row::new(vec![
+        "Vehicle", "GPS", "Home", "Gyro", "Accel", "Mag", "Battery", "Armable", "Result",
+    ])
+    .style(Style::default().fg(Color::Blue));
+    let rows: Vec<Row> = state
+        .vehicles
+        .iter()
+        .zip(state.health.iter())
+        .map(|(name, health)| {
+            let mut cells = vec![Cell::from(name.as_str())];
+            let checks = match health {
+                Some(health) => preflight_checks(health, state.min_battery),
+                None => {
+                    cells.push(Cell::from("waiting for health report"));
+                    return Row::new(cells);
+                }
+            };
+            let pass = checks.iter().all(|(_, pass)| *pass);
+            for (value, pass) in checks {
+                let color = if pass { Color::Green } else { Color::Red };
+                cells.push(Cell::from(value).style(Style::default().fg(color)));
+            }
+            let (result, color) = if pass {
+                ("PASS", Color::Green)
+            } else {
+                ("FAIL", Color::Red)
+            };
+            cells.push(Cell::from(result).style(Style::default().fg(color)));
+            Row::new(cells)
+        })
+        .collect();
+    let title = if state.signed_off {
+        "Preflight - signed off, press s to start"
+    } else {
+        "Preflight - press c to sign off"
+    };
+    let table = Table::new(rows)
+        .header(header)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .widths(&[
+            Constraint::Percentage(20),
+            Constraint::Percentage(15),
+            Constraint::Percentage(8),
+            Constraint::Percentage(8),
+            Constraint::Percentage(8),
+            Constraint::Percentage(8),
+            Constraint::Percentage(10),
+            Constraint::Percentage(10),
+            Constraint::Percentage(8),
+        ]);
+    f.render_widget(table, area)
+}
+
+// draw_preflight_mismatches lists the mission items that differ from the plan after upload
+fn draw_preflight_mismatches<B: Backend>(f: &mut Frame<B>, run_app: &RunApp, area: Rect) {
+    let state = run_app.state.read().unwrap();
+    let items: Vec<ListItem> = state
+        .mismatches
+        .iter()
+        .map(|(id, item, reason)| {
+            let item = if *item < 0 {
+                String::from("mission")
+            } else {
+                format!("item {}", item)
+            };
+            ListItem::new(Spans::from(vec![
+                Span::styled(
+                    format!("[{:<2}]{:<9}", id, item),
+                    Style::default().fg(Color::Red),
+                ),
+                Span::raw(reason.as_str()),
+            ]))
+        })
+        .collect();
+    let items = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Upload verification, items differing from the plan"),
+    );
+    f.render_widget(items, area)
+}
+
+// RunApp handles the state of the data in run mode, it is shared as an Arc between the UI and
+// the vehicle workers
+pub struct RunApp {
+    pub title: &'static str,
+    state: RwLock<RunAppState>,
+    pub barrier: FleetBarrier,
+    // operator input, only the UI changes it
+    input: Mutex<RunAppInput>,
     pub enhanced_graphics: bool,
 }
 
+struct RunAppInput {
+    should_quit: bool,
+    tabs: TabState<'static>,
+    // set once T-zero passed without a preflight sign-off has been logged
+    t_zero_held: bool,
+    // vehicle the operator commands, None for the whole fleet
+    selected: Option<usize>,
+    // command waiting for the operator to confirm, for the vehicle or None for the whole fleet
+    pending: Option<(Option<usize>, FleetCommand)>,
+    // mission item typed so far for a jump, None when not jumping
+    jump_input: Option<String>,
+}
+
 struct RunAppState {
+    // name and role of each vehicle, from the manifest
+    vehicles: Vec<String>,
     progress: Vec<f64>,
     logs: Vec<(usize, String)>,
+    // failures and the fleet actions taken for them
+    events: Vec<(usize, String)>,
+    fleet: Vec<FleetVehicle>,
+    telemetry: Vec<VehicleTelemetry>,
+    // latest preflight health of each vehicle, None until it reports
+    health: Vec<Option<HealthReport>>,
+    // (vehicle, item, difference) of each uploaded mission item that differs from the plan,
+    // item -1 for a different item count
+    mismatches: Vec<(usize, i32, String)>,
+    // battery percentage needed to pass preflight
+    min_battery: f32,
+    // set when the operator signs off the preflight checklist, the fleet only starts after it
+    signed_off: bool,
+    failure_policy: FailurePolicy,
+    // set once a fleet wide hold or return has been commanded
+    policy_applied: bool,
+}
+
+// VehicleTelemetry is the latest telemetry of a vehicle
+#[derive(Clone, Default)]
+struct VehicleTelemetry {
+    // (lat, lon, relative alt) of the last position
+    position: Option<(f64, f64, f32)>,
+    // None until the first telemetry report
+    report: Option<TelemetryReport>,
+    status_texts: Vec<String>,
+}
+
+impl VehicleTelemetry {
+    // summary is the one line shown beside the vehicle name
+    fn summary(&self) -> String {
+        let report = match &self.report {
+            Some(report) => report,
+            None => return String::from("[no telemetry]"),
+        };
+        let battery = if report.battery_percent.is_nan() {
+            String::from("?")
+        } else {
+            format!("{:.0}", report.battery_percent)
+        };
+        let state = match (report.armed, report.in_air) {
+            (_, true) => "in air",
+            (true, false) => "armed",
+            (false, false) => "disarmed",
+        };
+        let speed = report.velocity_north.hypot(report.velocity_east);
+        let altitude = self
+            .position
+            .map(|(_, _, alt)| format!(" {:.0} m", alt))
+            .unwrap_or_default();
+        format!(
+            "[{} {} | {}% | {} {} sats | {:03.0}° {:.1} m/s{}{}]",
+            flight_mode_name(report.flight_mode),
+            state,
+            battery,
+            fix_name(report.gps_fix_type),
+            report.satellites,
+            report.heading_deg,
+            speed,
+            altitude,
+            if report.rc_available { "" } else { " | no RC" },
+        )
+    }
+}
+
+// flight_mode_name names a MAVSDK Telemetry::FlightMode
+fn flight_mode_name(mode: i32) -> &'static str {
+    match mode {
+        1 => "Ready",
+        2 => "Takeoff",
+        3 => "Hold",
+        4 => "Mission",
+        5 => "Return",
+        6 => "Land",
+        7 => "Offboard",
+        8 => "Follow",
+        9 => "Manual",
+        10 => "Altitude",
+        11 => "Position",
+        12 => "Acro",
+        13 => "Stabilized",
+        14 => "Rattitude",
+        _ => "Unknown",
+    }
+}
+
+// fix_name names a MAVSDK Telemetry::FixType
+fn fix_name(fix_type: i32) -> &'static str {
+    match fix_type {
+        0 => "no GPS",
+        1 => "no fix",
+        2 => "2D fix",
+        3 => "3D fix",
+        4 => "DGPS",
+        5 => "RTK float",
+        _ => "RTK fixed",
+    }
+}
+
+// VehicleSystem lets the failure policy command the vehicles of other workers, MAVSDK systems
+// can be commanded from any thread
+#[derive(Clone, Copy)]
+struct VehicleSystem(*mut mavsdk::SHandle);
+
+unsafe impl Send for VehicleSystem {}
+unsafe impl Sync for VehicleSystem {}
+
+// FleetVehicle is what the failure policy needs to command a vehicle
+struct FleetVehicle {
+    system: VehicleSystem,
+    // MAV_AUTOPILOT and MAV_TYPE of the vehicle's plan, resuming differs between them
+    firmware: i32,
+    vehicle_type: i32,
+    // (lat, lon, alt, amsl) of the vehicle's slot, the last waypoint it holds at
+    slot: Option<(f64, f64, f64, bool)>,
+    failed: bool,
+    // set once the vehicle is past the barrier and its launch delay, vehicles not started are
+    // still on the ground and only need the barrier cancelled
+    started: bool,
+    // set while flightctl has taken the vehicle off its mission, so its return or landing is
+    // not taken for an autopilot failsafe
+    commanded: bool,
 }
 
-impl<'a> RunApp<'a> {
-    pub(crate) fn new(title: &'a str, enhanced_graphics: bool) -> RunApp {
+impl RunApp {
+    pub(crate) fn new(title: &'static str, enhanced_graphics: bool) -> RunApp {
         RunApp {
             title,
-            should_quit: false,
-            tabs: TabState::new(vec!["Overview", "Connections"]),
             state: RwLock::new(RunAppState {
+                vehicles: vec![],
                 progress: vec![],
                 logs: vec![(0, String::from("Loading Mavsdk"))],
+                events: vec![],
+                fleet: vec![],
+                telemetry: vec![],
+                health: vec![],
+                mismatches: vec![],
+                min_battery: 0.0,
+                signed_off: false,
+                failure_policy: FailurePolicy::Continue,
+                policy_applied: false,
+            }),
+            barrier: FleetBarrier::default(),
+            input: Mutex::new(RunAppInput {
+                should_quit: false,
+                tabs: TabState::new(vec!["Overview", "Preflight"]),
+                t_zero_held: false,
+                selected: None,
+                pending: None,
+                jump_input: None,
             }),
             enhanced_graphics,
         }
     }
 
-    fn on_key(&mut self, c: char) {
-        match c {
+    fn should_quit(&self) -> bool {
+        self.input.lock().unwrap().should_quit
+    }
+
+    // on_key and on_enter only hold the input lock to read the key's command, sending it waits
+    // on the vehicles and would otherwise stall the draw loop
+    fn on_key(&self, c: char) {
+        if let Some((target, command)) = self.key_command(c) {
+            self.command(target, command);
+        }
+    }
+
+    // key_command updates the input for key c, returning the command it confirms or sends
+    fn key_command(&self, c: char) -> Option<(Option<usize>, FleetCommand)> {
+        let mut input = self.input.lock().unwrap();
+        if let Some(item) = &mut input.jump_input {
+            if c.is_ascii_digit() {
+                item.push(c);
+            }
+            return None;
+        }
+        if let Some((target, command)) = input.pending.take() {
+            if c == 'y' {
+                return Some((target, command));
+            }
+            self.log(0, format!("{:?} cancelled", command));
+            return None;
+        }
+        let command = match c {
+            'h' => FleetCommand::Hold,
+            'r' => FleetCommand::Rtl,
+            'l' => FleetCommand::Land,
+            'm' => FleetCommand::Resume,
+            'p' => FleetCommand::Pause,
             'q' => {
-                self.should_quit = true;
+                // vehicles still waiting at the barrier or on their launch delay are not started
+                self.barrier.cancel();
+                input.should_quit = true;
+                return None;
+            }
+            'c' => {
+                self.sign_off();
+                return None;
+            }
+            's' => {
+                self.start_fleet();
+                return None;
+            }
+            // restarting flies the mission again from takeoff, so it is confirmed like a kill
+            'b' => {
+                input.pending = Some((input.selected, FleetCommand::Restart));
+                return None;
+            }
+            'j' => {
+                input.jump_input = Some(String::new());
+                return None;
+            }
+            // killing drops the vehicle, so it waits for the operator to confirm
+            'k' => {
+                input.pending = Some((input.selected, FleetCommand::Kill));
+                return None;
+            }
+            _ => return None,
+        };
+        Some((input.selected, command))
+    }
+
+    pub fn on_enter(&self) {
+        let (selected, item) = {
+            let mut input = self.input.lock().unwrap();
+            (input.selected, input.jump_input.take())
+        };
+        if let Some(item) = item {
+            match item.parse() {
+                Ok(item) => self.command(selected, FleetCommand::JumpTo(item)),
+                Err(_) => self.log(0, String::from("Jump cancelled, no mission item given")),
             }
-            _ => {}
         }
     }
 
-    pub fn on_left(&mut self) {
-        self.tabs.prev();
+    pub fn on_esc(&self) {
+        let mut input = self.input.lock().unwrap();
+        input.jump_input = None;
+        if let Some((_, command)) = input.pending.take() {
+            self.log(0, format!("{:?} cancelled", command));
+        }
+    }
+
+    pub fn on_backspace(&self) {
+        if let Some(item) = &mut self.input.lock().unwrap().jump_input {
+            item.pop();
+        }
+    }
+
+    // on_up and on_down move the selection through the whole fleet and then each vehicle
+    pub fn on_up(&self) {
+        let count = self.state.read().unwrap().vehicles.len();
+        let mut input = self.input.lock().unwrap();
+        input.selected = match input.selected {
+            None => count.checked_sub(1),
+            Some(0) => None,
+            Some(i) => Some(i - 1),
+        };
+    }
+
+    pub fn on_down(&self) {
+        let count = self.state.read().unwrap().vehicles.len();
+        let mut input = self.input.lock().unwrap();
+        input.selected = match input.selected {
+            None if count > 0 => Some(0),
+            Some(i) if i + 1 < count => Some(i + 1),
+            _ => None,
+        };
+    }
+
+    fn target_name(&self, target: Option<usize>) -> String {
+        let state = self.state.read().unwrap();
+        match target.and_then(|id| state.vehicles.get(id)) {
+            Some(name) => name.clone(),
+            None => String::from("whole fleet"),
+        }
+    }
+
+    fn selection_title(&self, selected: Option<usize>) -> String {
+        format!(
+            "{} | Selected: {} | up/down select, h hold, r rtl, l land, p pause, m resume, \
+             b restart, j jump, k kill",
+            self.title,
+            self.target_name(selected)
+        )
+    }
+
+    fn log(&self, id: usize, msg: String) {
+        self.state.write().unwrap().logs.push((id, msg));
+    }
+
+    // command sends an operator command to the target vehicle, or every vehicle for None
+    // every operator action goes through here, whatever interface it comes from
+    fn command(&self, target: Option<usize>, command: FleetCommand) {
+        let commands = {
+            let state = self.state.read().unwrap();
+            state
+                .fleet
+                .iter()
+                .enumerate()
+                .filter(|(id, _)| target.map(|target| target == *id).unwrap_or(true))
+                .map(|(id, vehicle)| (id, vehicle.system, command))
+                .collect()
+        };
+        self.send_commands(commands, "by operator");
     }
 
-    pub fn on_right(&mut self) {
-        self.tabs.next();
+    pub fn on_left(&self) {
+        self.input.lock().unwrap().tabs.prev();
     }
 
-    fn on_tick(&mut self) {}
+    pub fn on_right(&self) {
+        self.input.lock().unwrap().tabs.next();
+    }
+
+    fn on_tick(&self) {
+        if !self.barrier.t_zero_reached() {
+            return;
+        }
+        let mut input = self.input.lock().unwrap();
+        if self.state.read().unwrap().signed_off {
+            self.start_fleet();
+        } else if !input.t_zero_held {
+            input.t_zero_held = true;
+            let mut state = self.state.write().unwrap();
+            state.logs.push((
+                0,
+                String::from("T-zero reached without preflight sign-off, holding"),
+            ));
+        }
+    }
+
+    // sign_off signs off the preflight checklist once every vehicle still in the run passes
+    fn sign_off(&self) {
+        let mut state = self.state.write().unwrap();
+        let failing: Vec<usize> = state
+            .health
+            .iter()
+            .zip(state.fleet.iter())
+            .enumerate()
+            .filter(|(_, (health, vehicle))| {
+                !vehicle.failed
+                    && !health.is_some_and(|health| preflight_passed(&health, state.min_battery))
+            })
+            .map(|(id, _)| id)
+            .collect();
+        if failing.is_empty() {
+            state.signed_off = true;
+            state
+                .logs
+                .push((0, String::from("Preflight checklist signed off")));
+            return;
+        }
+        for id in failing {
+            state.logs.push((
+                id,
+                String::from("Preflight checks failing, cannot sign off"),
+            ));
+        }
+    }
+
+    // start_fleet releases the barrier so every ready vehicle arms and starts together, and
+    // reports the vehicles that did not reach it
+    fn start_fleet(&self) {
+        if !self.state.read().unwrap().signed_off {
+            let mut state = self.state.write().unwrap();
+            state.logs.push((
+                0,
+                String::from("Sign off the preflight checklist with c before starting"),
+            ));
+            return;
+        }
+        let missing = match self.barrier.release(true) {
+            Some(missing) => missing,
+            None => return,
+        };
+        let mut state = self.state.write().unwrap();
+        state.logs.push((0, String::from("Starting fleet")));
+        for id in missing {
+            state.logs.push((
+                id,
+                String::from("Did not reach the start barrier, not started"),
+            ));
+        }
+    }
+
+    // load_manifest sets up a progress gauge for every vehicle in the manifest
+    fn load_manifest(&self, manifest: &Manifest) {
+        let mut state = self.state.write().unwrap();
+        state.vehicles = manifest
+            .vehicles
+            .iter()
+            .map(|v| {
+                if v.role.is_empty() {
+                    v.name.clone()
+                } else {
+                    format!("{} ({})", v.name, v.role)
+                }
+            })
+            .collect();
+        state.progress = vec![0.0; manifest.vehicles.len()];
+        state.fleet = manifest
+            .vehicles
+            .iter()
+            .map(|v| FleetVehicle {
+                system: VehicleSystem(std::ptr::null_mut()),
+                firmware: 0,
+                vehicle_type: 0,
+                slot: v.load_plan().ok().and_then(|plan| plan_slot(&plan)),
+                failed: false,
+                started: false,
+                commanded: false,
+            })
+            .collect();
+        state.telemetry = vec![VehicleTelemetry::default(); manifest.vehicles.len()];
+        state.health = vec![None; manifest.vehicles.len()];
+        state.min_battery = manifest.options.min_battery;
+        state.signed_off = false;
+        state.failure_policy = manifest.options.failure_policy;
+        self.barrier
+            .reset(manifest.vehicles.len(), manifest.options.t_zero);
+    }
+}
+
+impl RunApp {
+    fn register_system(
+        &self,
+        id: usize,
+        system: *mut mavsdk::SHandle,
+        firmware: i32,
+        vehicle_type: i32,
+    ) {
+        let mut state = self.state.write().unwrap();
+        if let Some(vehicle) = state.fleet.get_mut(id) {
+            vehicle.system = VehicleSystem(system);
+            vehicle.firmware = firmware;
+            vehicle.vehicle_type = vehicle_type;
+        }
+    }
+
+    // apply_failure_policy logs the failure of vehicle id and commands the fleet according to
+    // the failure policy, every command is logged as an event
+    fn apply_failure_policy(&self, id: usize, failure: Failure) {
+        let mut commands = vec![];
+        {
+            let mut state = self.state.write().unwrap();
+            match state.fleet.get_mut(id) {
+                Some(vehicle) if !vehicle.failed => vehicle.failed = true,
+                _ => return,
+            }
+            state.events.push((id, format!("{:?} failure", failure)));
+            let policy = state.failure_policy;
+            match policy {
+                FailurePolicy::Continue => {
+                    state
+                        .events
+                        .push((id, String::from("Continuing without vehicle")));
+                }
+                FailurePolicy::Hold | FailurePolicy::Rtl => {
+                    // a vehicle lost before the start keeps the rest of the fleet on the ground
+                    if failure.before_start() && self.barrier.cancel() {
+                        state.events.push((
+                            id,
+                            format!("Cancelled the fleet start after vehicle {} failed", id),
+                        ));
+                    }
+                    if !state.policy_applied {
+                        state.policy_applied = true;
+                        let started = state.fleet.iter().enumerate().filter(|(_, v)| v.started);
+                        for (other, vehicle) in started {
+                            let command = if policy == FailurePolicy::Hold {
+                                FleetCommand::Hold
+                            } else {
+                                FleetCommand::Rtl
+                            };
+                            commands.push((other, vehicle.system, command));
+                        }
+                    }
+                }
+                FailurePolicy::CloseUp => {
+                    if state.fleet[id].started {
+                        commands.push((id, state.fleet[id].system, FleetCommand::Land));
+                    }
+                    // the remaining vehicles take the slots in order, closing the gap
+                    let slots: Vec<_> = state.fleet.iter().map(|v| v.slot).collect();
+                    let survivors = state.fleet.iter().enumerate().filter(|(_, v)| !v.failed);
+                    for (slot, (other, vehicle)) in slots.into_iter().zip(survivors) {
+                        if !vehicle.started {
+                            continue;
+                        }
+                        if let Some(slot) = slot.filter(|slot| Some(*slot) != vehicle.slot) {
+                            commands.push((other, vehicle.system, FleetCommand::Goto(slot)));
+                        }
+                    }
+                }
+            }
+        }
+
+        self.send_commands(commands, format!("after vehicle {} failed", id).as_str());
+    }
+
+    // set_commanded marks vehicle id as taken off its mission by flightctl, or back on it
+    fn set_commanded(&self, id: usize, commanded: bool) {
+        let mut state = self.state.write().unwrap();
+        if let Some(vehicle) = state.fleet.get_mut(id) {
+            vehicle.commanded = commanded;
+        }
+    }
+
+    // send_commands commands each vehicle, logging every command as an event with the reason
+    fn send_commands(&self, commands: Vec<(usize, VehicleSystem, FleetCommand)>, reason: &str) {
+        for (id, VehicleSystem(system), command) in commands {
+            if system.is_null() {
+                continue;
+            }
+            // marked before sending, the mode can change before the command returns
+            let leaves_mission = command.leaves_mission();
+            if leaves_mission {
+                self.set_commanded(id, true);
+            }
+            let (firmware, vehicle_type) = {
+                let state = self.state.read().unwrap();
+                (state.fleet[id].firmware, state.fleet[id].vehicle_type)
+            };
+            let result = unsafe {
+                match command {
+                    FleetCommand::Hold => mavsdk::action_hold(system),
+                    FleetCommand::Rtl => mavsdk::action_return_to_launch(system),
+                    FleetCommand::Land => mavsdk::action_land(system),
+                    FleetCommand::Resume => mavsdk::action_resume(system, firmware, vehicle_type),
+                    FleetCommand::Kill => mavsdk::action_kill(system),
+                    FleetCommand::Pause => mavsdk::mission_pause(system, firmware, vehicle_type),
+                    FleetCommand::Restart => {
+                        match mavsdk::mission_set_current(system, firmware, 0) {
+                            0 => mavsdk::action_resume(system, firmware, vehicle_type),
+                            result => result,
+                        }
+                    }
+                    FleetCommand::JumpTo(item) => {
+                        mavsdk::mission_set_current(system, firmware, item)
+                    }
+                    FleetCommand::Goto((lat, lon, alt, amsl)) => {
+                        mavsdk::action_goto(system, lat, lon, alt as f32, amsl)
+                    }
+                }
+            };
+            // back on the mission once resumed, or still on it when the command failed
+            if (result == 0) != leaves_mission {
+                self.set_commanded(id, false);
+            }
+            let outcome = if result == 0 {
+                "Commanded"
+            } else {
+                "Failed to command"
+            };
+            let mut state = self.state.write().unwrap();
+            state
+                .events
+                .push((id, format!("{} {:?} {}", outcome, command, reason)));
+        }
+    }
+}
+
+// satellites needed, with a 3D fix, to pass preflight
+const MIN_SATELLITES: i32 = 6;
+
+// preflight_checks reads a health report into the checklist, a (value, pass) pair for the GPS,
+// home, gyro, accelerometer, magnetometer, battery and armable checks
+fn preflight_checks(health: &HealthReport, min_battery: f32) -> Vec<(String, bool)> {
+    let calibration = |ok: bool| {
+        let value = if ok { "ok" } else { "uncalibrated" };
+        (value.to_string(), ok)
+    };
+    let fix = fix_name(health.gps_fix_type);
+    let battery = if health.battery_percent.is_nan() {
+        (String::from("unknown"), false)
+    } else {
+        (
+            format!("{:.0}%", health.battery_percent),
+            health.battery_percent >= min_battery,
+        )
+    };
+    vec![
+        (
+            format!("{}, {} sats", fix, health.satellites),
+            health.gps_fix_type >= 3 && health.satellites >= MIN_SATELLITES,
+        ),
+        (
+            String::from(if health.home_ok { "set" } else { "not set" }),
+            health.home_ok,
+        ),
+        calibration(health.gyro_ok),
+        calibration(health.accel_ok),
+        calibration(health.mag_ok),
+        battery,
+        (
+            String::from(if health.armable { "yes" } else { "no" }),
+            health.armable,
+        ),
+    ]
+}
+
+fn preflight_passed(health: &HealthReport, min_battery: f32) -> bool {
+    preflight_checks(health, min_battery)
+        .iter()
+        .all(|(_, pass)| *pass)
+}
+
+// FleetCommand is a command the failure policy sends to a vehicle
+#[derive(Clone, Copy, Debug)]
+enum FleetCommand {
+    Hold,
+    Rtl,
+    Land,
+    // continue the mission from its current item
+    Resume,
+    // stop the motors at once
+    Kill,
+    // hold on the mission, resume continues from the current item
+    Pause,
+    // fly the mission again from its first item
+    Restart,
+    // continue the mission from an item, counting from 0 as in the plan
+    JumpTo(i32),
+    Goto((f64, f64, f64, bool)),
+}
+
+impl FleetCommand {
+    // leaves_mission is true for the commands that take the vehicle off its mission
+    fn leaves_mission(&self) -> bool {
+        !matches!(
+            self,
+            FleetCommand::Resume | FleetCommand::Restart | FleetCommand::JumpTo(_)
+        )
+    }
 }
 
-pub async fn start_workers<'a>(
-    arc: Arc<&'a mut RunApp<'a>>,
-    sets: Vec<(String, String)>,
-) -> JoinAll<JoinHandle<Result<(), ()>>> {
+// FailurePolicy is what the fleet does when one vehicle fails
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq)]
+pub enum FailurePolicy {
+    // only log the failure, the rest of the fleet carries on
+    Continue,
+    // hold every vehicle in place
+    Hold,
+    // return every vehicle to launch
+    Rtl,
+    // land the failed vehicle and move the vehicles after it up a slot
+    CloseUp,
+}
+
+// Failure is what went wrong with a vehicle
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Failure {
+    Setup,
+    Upload,
+    // the mission downloaded after the upload differs from the plan
+    Verify,
+    Arm,
+    LinkLost,
+    Failsafe,
+}
+
+impl Failure {
+    // from_code reads the run_qgc_plan return codes and the failure callback kinds
+    fn from_code(code: i32) -> Option<Failure> {
+        match code {
+            -1 => Some(Failure::Setup),
+            -2 => Some(Failure::Upload),
+            -3 => Some(Failure::Arm),
+            -5 => Some(Failure::Verify),
+            1 => Some(Failure::LinkLost),
+            2 => Some(Failure::Failsafe),
+            _ => None,
+        }
+    }
+
+    // before_start is true for the failures of a vehicle that never started its mission
+    fn before_start(&self) -> bool {
+        matches!(
+            self,
+            Failure::Setup | Failure::Upload | Failure::Verify | Failure::Arm
+        )
+    }
+}
+
+// FleetBarrier holds every vehicle after its upload until the whole fleet is released, by the
+// operator or at T-zero, so all vehicles arm and start together
+#[derive(Default)]
+pub struct FleetBarrier {
+    state: Mutex<BarrierState>,
+    released: Condvar,
+}
+
+#[derive(Default)]
+struct BarrierState {
+    ready: Vec<bool>,
+    // set once released, true when the vehicles are to start
+    start: Option<bool>,
+    // when the fleet was started, launch delays count from it
+    started_at: Option<Instant>,
+    // unix time in seconds at which the fleet is released without the operator
+    t_zero: Option<u64>,
+}
+
+impl FleetBarrier {
+    fn reset(&self, count: usize, t_zero: Option<u64>) {
+        let mut state = self.state.lock().unwrap();
+        *state = BarrierState {
+            ready: vec![false; count],
+            start: None,
+            started_at: None,
+            t_zero,
+        };
+    }
+
+    // arrive marks the vehicle ready and blocks until the fleet is released
+    // returns true when the vehicle is to start, false when released without it
+    fn arrive(&self, id: usize) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.start.is_some() {
+            return false;
+        }
+        if let Some(ready) = state.ready.get_mut(id) {
+            *ready = true;
+        }
+        while state.start.is_none() {
+            state = self.released.wait(state).unwrap();
+        }
+        state.start == Some(true)
+    }
+
+    // release wakes every waiting vehicle, returns the vehicles that were not ready or None
+    // when the barrier was already released
+    fn release(&self, start: bool) -> Option<Vec<usize>> {
+        let mut state = self.state.lock().unwrap();
+        if state.start.is_some() {
+            return None;
+        }
+        state.start = Some(start);
+        state.started_at = Some(Instant::now());
+        self.released.notify_all();
+        Some(
+            state
+                .ready
+                .iter()
+                .enumerate()
+                .filter(|(_, ready)| !**ready)
+                .map(|(id, _)| id)
+                .collect(),
+        )
+    }
+
+    // wait_launch blocks until delay after the fleet start, returns false when the start is
+    // cancelled first
+    fn wait_launch(&self, delay: Duration) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let started = state.started_at.unwrap_or_else(Instant::now);
+        while state.start == Some(true) {
+            let elapsed = started.elapsed();
+            if elapsed >= delay {
+                return true;
+            }
+            state = self
+                .released
+                .wait_timeout(state, delay - elapsed)
+                .unwrap()
+                .0;
+        }
+        false
+    }
+
+    // cancel releases the barrier without starting, also stopping vehicles still waiting out
+    // their launch delay, returns false when the start was already cancelled
+    fn cancel(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.start == Some(false) {
+            return false;
+        }
+        state.start = Some(false);
+        self.released.notify_all();
+        true
+    }
+
+    fn is_released(&self) -> bool {
+        self.state.lock().unwrap().start.is_some()
+    }
+
+    fn t_zero_reached(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        state.start.is_none() && state.t_zero.is_some_and(|t_zero| now >= t_zero)
+    }
+}
+
+// FenceAction is the command sent to a vehicle when flightctl sees it breach the geofence
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq)]
+pub enum FenceAction {
+    // only log the breach
+    Report,
+    Hold,
+    Rtl,
+}
+
+// RunOptions holds the settings shared by every worker in run mode
+#[derive(Clone)]
+pub struct RunOptions {
+    // geofence uploaded to every vehicle instead of the geofence in its plan
+    pub fence: Option<Fence>,
+    pub fence_action: FenceAction,
+    // return altitude of the first vehicle instead of the return altitude in each plan
+    pub return_alt: Option<f32>,
+    // meters each vehicle returns higher than the vehicle before it with return_alt
+    pub return_alt_step: f32,
+    // seconds to wait for each vehicle to be discovered
+    pub timeout: u32,
+    // unix time in seconds to start the fleet at, the operator starts it when unset
+    pub t_zero: Option<u64>,
+    pub failure_policy: FailurePolicy,
+    // battery percentage every vehicle needs to pass preflight
+    pub min_battery: f32,
+    pub telemetry_rates: TelemetryRates,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        RunOptions {
+            fence: None,
+            fence_action: FenceAction::Rtl,
+            return_alt: None,
+            return_alt_step: 5.0,
+            timeout: 3,
+            t_zero: None,
+            failure_policy: FailurePolicy::Continue,
+            min_battery: 50.0,
+            telemetry_rates: TelemetryRates::default(),
+        }
+    }
+}
+
+// TelemetryRate is the rate of one telemetry stream, given as stream=hz such as battery=2
+#[derive(Clone, Copy, Debug)]
+pub struct TelemetryRate {
+    stream: RateStream,
+    hz: f64,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum RateStream {
+    Position,
+    Battery,
+    GpsInfo,
+    InAir,
+    Velocity,
+    RcStatus,
+}
+
+impl TelemetryRate {
+    pub fn apply(self, rates: &mut TelemetryRates) {
+        let rate = match self.stream {
+            RateStream::Position => &mut rates.position,
+            RateStream::Battery => &mut rates.battery,
+            RateStream::GpsInfo => &mut rates.gps_info,
+            RateStream::InAir => &mut rates.in_air,
+            RateStream::Velocity => &mut rates.velocity,
+            RateStream::RcStatus => &mut rates.rc_status,
+        };
+        *rate = self.hz;
+    }
+}
+
+impl FromStr for TelemetryRate {
+    type Err = String;
+
+    // from_str reads rates such as position=5, battery=0.5, gps=1, in-air=1, velocity=2 or rc=1
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid telemetry rate {}", text);
+        let (stream, hz) = text.split_once('=').ok_or_else(invalid)?;
+        let stream = match stream.trim().to_lowercase().replace('_', "-").as_str() {
+            "position" => RateStream::Position,
+            "battery" => RateStream::Battery,
+            "gps" | "gps-info" => RateStream::GpsInfo,
+            "in-air" => RateStream::InAir,
+            "velocity" => RateStream::Velocity,
+            "rc" | "rc-status" => RateStream::RcStatus,
+            _ => return Err(invalid()),
+        };
+        let hz: f64 = hz.trim().parse().map_err(|_| invalid())?;
+        if hz <= 0.0 {
+            return Err(invalid());
+        }
+        Ok(TelemetryRate { stream, hz })
+    }
+}
+
+// DO_JUMP, its first param is the doJumpId of the target in a plan and the item index on the
+// vehicle
+const CMD_DO_JUMP: u64 = 177;
+
+// mission_items reads the items of a QGC plan into the raw items uploaded to the vehicle, only
+// simple items are supported, as with MAVSDK's own plan import
+fn mission_items(plan: &Value) -> io::Result<Vec<RawItem>> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+    let items = plan["mission"]["items"]
+        .as_array()
+        .ok_or_else(|| invalid(String::from("No mission items in plan")))?;
+    // doJumpIds are only unique labels, they need not follow the item order
+    let index_of: HashMap<u64, usize> = items
+        .iter()
+        .enumerate()
+        .filter_map(|(i, item)| item["doJumpId"].as_u64().map(|id| (id, i)))
+        .collect();
+    items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| {
+            if item["type"].as_str() != Some("SimpleItem") {
+                return Err(invalid(format!("Mission item {} is not a simple item", i)));
+            }
+            let command = item["command"]
+                .as_u64()
+                .ok_or_else(|| invalid(format!("Mission item {} has no command", i)))?;
+            // QGC writes NaN params as null
+            let param = |j: usize| item["params"][j].as_f64().unwrap_or(f64::NAN) as f32;
+            let position = |j: usize| item["params"][j].as_f64().unwrap_or(0.0);
+            let mut param1 = param(0);
+            if command == CMD_DO_JUMP {
+                let target = &item["params"][0];
+                let index = target.as_u64().and_then(|id| index_of.get(&id));
+                param1 = *index.ok_or_else(|| {
+                    invalid(format!(
+                        "Mission item {} jumps to missing item {}",
+                        i, target
+                    ))
+                })? as f32;
+            }
+            let frame = item["frame"].as_u64().unwrap_or(0) as u32;
+            let scale = position_scale(frame);
+            Ok(RawItem {
+                mission_type: 0,
+                seq: i as u32,
+                frame,
+                command: command as u32,
+                autocontinue: item["autoContinue"].as_bool().unwrap_or(true),
+                param1,
+                param2: param(1),
+                param3: param(2),
+                param4: param(3),
+                x: (position(4) * scale).round() as i32,
+                y: (position(5) * scale).round() as i32,
+                z: position(6) as f32,
+            })
+        })
+        .collect()
+}
+
+// plan_vehicle reads the MAV_AUTOPILOT firmwareType and MAV_TYPE vehicleType of a QGC plan
+// plans without them are treated as a PX4 multirotor
+fn plan_vehicle(plan: &Value) -> (i32, i32) {
+    let firmware = plan["mission"]["firmwareType"].as_i64().unwrap_or(12);
+    let vehicle_type = plan["mission"]["vehicleType"].as_i64().unwrap_or(2);
+    (firmware as i32, vehicle_type as i32)
+}
+
+// plan_slot reads the slot of a plan, its last waypoint with a hold or else its last waypoint
+// before the return, as (lat, lon, alt, amsl)
+fn plan_slot(plan: &Value) -> Option<(f64, f64, f64, bool)> {
+    let items = plan["mission"]["items"].as_array()?;
+    let end = items
+        .iter()
+        .position(|item| matches!(item["command"].as_u64(), Some(20) | Some(21) | Some(189)))
+        .unwrap_or(items.len());
+    let waypoints: Vec<&Value> = items[..end]
+        .iter()
+        .filter(|item| item["command"].as_u64() == Some(16))
+        .collect();
+    let slot = waypoints
+        .iter()
+        .rev()
+        .find(|item| item["params"][0].as_f64().unwrap_or(0.0) > 0.0)
+        .or(waypoints.last())?;
+    let params = &slot["params"];
+    Some((
+        params[4].as_f64()?,
+        params[5].as_f64()?,
+        params[6].as_f64()?,
+        slot["frame"].as_u64() == Some(0),
+    ))
+}
+
+// return_alt is the return altitude set on vehicle id, from the options when given so every
+// vehicle is stepped up from the first, otherwise from the returnAltitude flightctl wrote
+// into its plan
+fn return_alt(options: &RunOptions, id: usize, plan: &Value) -> Option<f32> {
+    match options.return_alt {
+        Some(alt) => Some(alt + options.return_alt_step * id as f32),
+        None => plan["returnAltitude"].as_f64().map(|alt| alt as f32),
+    }
+}
+
+// upload_fence sends the fence to the vehicle through the geofence plugin
+fn upload_fence(system: *mut mavsdk::SHandle, fence: &Fence) -> bool {
+    let polygons = fence.to_polygons();
+    let mut lats = vec![];
+    let mut lons = vec![];
+    let mut sizes = vec![];
+    let mut inclusions = vec![];
+    for polygon in &polygons {
+        sizes.push(polygon.vertices.len() as i32);
+        inclusions.push(polygon.inclusion);
+        for (lat, lon) in &polygon.vertices {
+            lats.push(*lat);
+            lons.push(*lon);
+        }
+    }
+    let result = unsafe {
+        mavsdk::upload_geofence(
+            system,
+            lats.as_ptr(),
+            lons.as_ptr(),
+            sizes.as_ptr(),
+            inclusions.as_ptr(),
+            polygons.len() as i32,
+        )
+    };
+    result == 0
+}
+
+// start_workers starts a worker for every vehicle, each runs on a blocking thread as MAVSDK
+// blocks until its mission is done
+pub fn start_workers(app: Arc<RunApp>, manifest: Manifest) -> JoinAll<JoinHandle<Result<(), ()>>> {
     let mut futures = vec![];
-    let id = 0;
-    for (uri, plan) in sets {
-        let f = tokio::spawn(async move {
-            let arc = Arc::clone(&arc);
-            let path = Path::new(plan.as_str());
-            if !(path.exists() && path.is_file()) {
-                panic!(
-                    "Error, path file {} does not exist or is not a file",
-                    path.display()
+    for (id, vehicle) in manifest.vehicles.into_iter().enumerate() {
+        let options = manifest.options.clone();
+        let app = Arc::clone(&app);
+        let f = tokio::task::spawn_blocking(move || {
+            let uri = vehicle.uri.clone();
+            // the plan is parsed here, generated plans are never written to disk
+            let plan = match vehicle.load_plan() {
+                Ok(plan) => plan,
+                Err(e) => {
+                    app.log(id, format!("Failed to load {}: {}", vehicle.plan, e));
+                    app.apply_failure_policy(id, Failure::Setup);
+                    return Ok(());
+                }
+            };
+            let items = match mission_items(&plan) {
+                Ok(items) => items,
+                Err(e) => {
+                    app.log(id, format!("Failed to read {}: {}", vehicle.plan, e));
+                    app.apply_failure_policy(id, Failure::Upload);
+                    return Ok(());
+                }
+            };
+            let sdk = unsafe { mavsdk::new_mavsdk() };
+            let system = unsafe {
+                mavsdk::connect(
+                    sdk,
+                    CString::new(uri.clone()).unwrap().as_ptr(),
+                    options.timeout as i32,
                 )
+            };
+            let rtl_alt = return_alt(&options, id, &plan);
+            // a geofence given on the command line replaces the one in the plan
+            let fence = match options.fence {
+                Some(fence) => Some(fence),
+                None => Fence::from_qgc(&plan["geoFence"]).filter(|fence| !fence.is_empty()),
+            };
+            let worker = RunWorker::new(
+                Arc::clone(&app),
+                id,
+                system,
+                fence,
+                options.fence_action,
+                vehicle.launch_delay,
+            );
+            if system.is_null() {
+                worker.log("Failed to connect");
+                worker.failed(-1);
+                return Ok(());
             }
-            let sdk = unsafe { mavsdk::new_mavsdk() };
-            let system =
-                unsafe { mavsdk::connect(sdk, CString::new(uri.clone()).unwrap().as_ptr()) };
-            let worker = RunWorker::new(arc, id);
+            let (firmware, vehicle_type) = plan_vehicle(&plan);
+            app.register_system(id, system, firmware, vehicle_type);
+            if let Some(fence) = &worker.fence {
+                if upload_fence(system, fence) {
+                    worker.log("Uploaded geofence");
+                } else {
+                    worker.log("Failed to upload geofence");
+                }
+            }
+            // distinct return altitudes keep vehicles apart if the run is aborted
+            if let Some(alt) = rtl_alt {
+                let result =
+                    unsafe { mavsdk::set_return_altitude(system, firmware, vehicle_type, alt) };
+                if result == 0 {
+                    worker.log(format!("Set return altitude to {:.0} m", alt).as_str());
+                } else {
+                    worker.log("Failed to set return altitude");
+                }
+            }
+            // the callbacks borrow the worker, it is freed once the plan returns
             let ptr: Box<Box<dyn mavsdk::RunCallBackObject>> = Box::new(Box::new(worker));
+            let context = Box::into_raw(ptr);
 
-            unsafe {
+            let mission = unsafe { mavsdk::new_mission_items() };
+            for item in &items {
+                unsafe { mavsdk::add_mission_item(mission, item) };
+            }
+            let result = unsafe {
                 mavsdk::run_qgc_plan(
                     system,
-                    CString::new(uri).unwrap().as_ptr(),
-                    Box::into_raw(ptr) as *mut Box<dyn mavsdk::RunCallBackObject>,
+                    mission,
+                    context,
                     mavsdk::run_callback_position,
                     mavsdk::run_callback_progress,
                     mavsdk::run_callback_complete,
                     mavsdk::run_callback_log,
-                );
+                    mavsdk::run_callback_ready,
+                    mavsdk::run_callback_failure,
+                    mavsdk::run_callback_health,
+                    mavsdk::run_callback_telemetry,
+                    mavsdk::run_callback_mismatch,
+                    &options.telemetry_rates,
+                    firmware,
+                    vehicle_type,
+                )
             };
+            unsafe {
+                mavsdk::del_mission_items(mission);
+                drop(Box::from_raw(context));
+            }
+            if let Some(failure) = Failure::from_code(result) {
+                app.apply_failure_policy(id, failure);
+            }
             Ok(())
         });
         futures.push(f);
@@ -191,20 +1476,93 @@ pub async fn start_workers<'a>(
     join_all(futures)
 }
 
-struct RunWorker<'a> {
-    app: Arc<&'a mut RunApp<'a>>,
+struct RunWorker {
+    app: Arc<RunApp>,
     id: usize,
+    system: *mut mavsdk::SHandle,
+    fence: Option<Fence>,
+    fence_action: FenceAction,
+    // set while the vehicle is outside the fence so the action is commanded once per breach
+    breached: AtomicBool,
+    // seconds after the fleet start before this vehicle starts
+    launch_delay: f64,
 }
 
-impl<'a> RunWorker<'a> {
-    fn new(app: Arc<&'a mut RunApp<'a>>, id: usize) -> RunWorker<'a> {
-        RunWorker { app, id }
+impl RunWorker {
+    fn new(
+        app: Arc<RunApp>,
+        id: usize,
+        system: *mut mavsdk::SHandle,
+        fence: Option<Fence>,
+        fence_action: FenceAction,
+        launch_delay: f64,
+    ) -> RunWorker {
+        RunWorker {
+            app,
+            id,
+            system,
+            fence,
+            fence_action,
+            breached: AtomicBool::new(false),
+            launch_delay,
+        }
+    }
+
+    // check_sign_off withdraws the sign-off when this vehicle stops passing before the start,
+    // so the checklist has to be signed off again
+    fn check_sign_off(&self, data: &mut RunAppState) {
+        let passed = data.health[self.id].is_some_and(|h| preflight_passed(&h, data.min_battery));
+        if data.signed_off && !passed && !self.app.barrier.is_released() {
+            data.signed_off = false;
+            data.logs.push((
+                self.id,
+                String::from("Preflight check failed after sign-off, sign off again"),
+            ));
+        }
     }
 }
 
-impl<'a> mavsdk::RunCallBackObject for RunWorker<'a> {
+impl mavsdk::RunCallBackObject for RunWorker {
     fn save_position(&self, lat: f64, lon: f64, alt: f32) {
-        todo!()
+        {
+            let mut data = self.app.state.write().unwrap();
+            data.telemetry[self.id].position = Some((lat, lon, alt));
+        }
+        // ground side geofence check, a backstop for a misconfigured autopilot fence
+        let fence = match &self.fence {
+            Some(fence) => fence,
+            None => return,
+        };
+        if fence.contains(lat, lon) {
+            // back inside, so the next breach is acted on again
+            if self.breached.swap(false, Ordering::SeqCst) {
+                self.log("Back inside geofence");
+            }
+            return;
+        }
+        if self.breached.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        self.log(format!("Geofence breach at {:.7}, {:.7}", lat, lon).as_str());
+        let commanded = self.fence_action != FenceAction::Report;
+        self.app.set_commanded(self.id, commanded);
+        let result = unsafe {
+            match self.fence_action {
+                FenceAction::Report => 0,
+                FenceAction::Hold => mavsdk::action_hold(self.system),
+                FenceAction::Rtl => mavsdk::action_return_to_launch(self.system),
+            }
+        };
+        if result != 0 {
+            self.app.set_commanded(self.id, false);
+            self.log(
+                format!(
+                    "Failed to command {:?} after geofence breach",
+                    self.fence_action
+                )
+                .as_str(),
+            );
+        }
     }
 
     fn save_progress(&self, current: i32, total: i32) {
@@ -215,24 +1573,90 @@ impl<'a> mavsdk::RunCallBackObject for RunWorker<'a> {
         }
     }
 
+    fn save_health(&self, health: &HealthReport) {
+        let mut data = self.app.state.write().unwrap();
+        data.health[self.id] = Some(*health);
+        self.check_sign_off(&mut data);
+    }
+
+    fn save_telemetry(&self, stream: i32, report: &TelemetryReport, text: &str) {
+        let mut data = self.app.state.write().unwrap();
+        let telemetry = &mut data.telemetry[self.id];
+        telemetry.report = Some(*report);
+        if stream == mavsdk::TELEMETRY_STATUS_TEXT {
+            telemetry.status_texts.push(text.to_string());
+        }
+        // the battery and GPS change without a health update, the checklist follows them
+        if let Some(health) = data.health[self.id].as_mut() {
+            match stream {
+                mavsdk::TELEMETRY_BATTERY => health.battery_percent = report.battery_percent,
+                mavsdk::TELEMETRY_GPS_INFO => {
+                    health.gps_fix_type = report.gps_fix_type;
+                    health.satellites = report.satellites;
+                }
+                _ => return,
+            }
+            self.check_sign_off(&mut data);
+        }
+    }
+
     fn log(&self, msg: &str) {
         let mut data = self.app.state.write().unwrap();
         data.logs.push((self.id, msg.to_string()));
     }
 
+    fn mismatch(&self, item: i32, reason: &str) {
+        let mut data = self.app.state.write().unwrap();
+        data.mismatches.push((self.id, item, reason.to_string()));
+    }
+
+    fn ready(&self) -> bool {
+        self.log("Ready, waiting for the fleet start");
+        if !self.app.barrier.arrive(self.id) {
+            self.log("Fleet released without this vehicle, not starting");
+            return false;
+        }
+        if self.launch_delay > 0.0 {
+            self.log(format!("Launching in {:.0} s", self.launch_delay).as_str());
+            let delay = Duration::from_secs_f64(self.launch_delay);
+            if !self.app.barrier.wait_launch(delay) {
+                self.log("Fleet start cancelled, not starting");
+                return false;
+            }
+        }
+        if let Some(vehicle) = self.app.state.write().unwrap().fleet.get_mut(self.id) {
+            vehicle.started = true;
+        }
+        true
+    }
+
+    fn failed(&self, kind: i32) {
+        let failure = match Failure::from_code(kind) {
+            Some(failure) => failure,
+            None => return,
+        };
+        // flightctl sent the vehicle home or down itself, it is not the autopilot's failsafe
+        let commanded = self.app.state.read().unwrap().fleet[self.id].commanded;
+        if matches!(failure, Failure::Failsafe) && commanded {
+            self.log("Left the mission as commanded");
+            return;
+        }
+        self.app.apply_failure_policy(self.id, failure);
+    }
+
     fn complete(&self) {
         println!("Worker {} done, Exiting", self.id)
     }
 }
 
-pub async fn run_app<'a, B: Backend>(
+pub async fn run_app<B: Backend>(
     terminal: &mut Terminal<B>,
-    app: &'a mut RunApp<'a>,
-    sets: Vec<(String, String)>,
+    app: Arc<RunApp>,
+    manifest: Manifest,
     tick_rate: Duration,
 ) -> io::Result<()> {
-    let arc = Arc::new(app);
-    let ret = start_workers(arc, sets);
+    app.load_manifest(&manifest);
+    let ret = start_workers(Arc::clone(&app), manifest);
     let mut last_tick = Instant::now();
     loop {
         terminal.draw(|f| draw(f, &app))?;
@@ -247,6 +1671,11 @@ pub async fn run_app<'a, B: Backend>(
                     KeyCode::Char(c) => app.on_key(c),
                     KeyCode::Left => app.on_left(),
                     KeyCode::Right => app.on_right(),
+                    KeyCode::Up => app.on_up(),
+                    KeyCode::Down => app.on_down(),
+                    KeyCode::Enter => app.on_enter(),
+                    KeyCode::Esc => app.on_esc(),
+                    KeyCode::Backspace => app.on_backspace(),
                     _ => {}
                 }
             }
@@ -255,9 +1684,60 @@ pub async fn run_app<'a, B: Backend>(
             app.on_tick();
             last_tick = Instant::now();
         }
-        if arc.should_quit {
+        if app.should_quit() {
             ret.await;
             return Ok(());
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn simple_item(frame: u64, command: u64, params: Value) -> Value {
+        json!({
+            "type": "SimpleItem",
+            "autoContinue": true,
+            "command": command,
+            "frame": frame,
+            "params": params,
+        })
+    }
+
+    #[test]
+    fn mission_items_scale_positions_by_frame() {
+        let plan = json!({
+            "mission": {
+                "items": [
+                    simple_item(3, 16, json!([0, 0, 0, null, 47.3977419, 8.5455938, 20])),
+                    simple_item(1, 16, json!([0, 0, 0, null, 12.5, -3.25, -10])),
+                    simple_item(2, 178, json!([1, 5, -1, 0, 7, 9, 0])),
+                ]
+            }
+        });
+        let items = mission_items(&plan).unwrap();
+        assert_eq!((items[0].x, items[0].y), (473977419, 85455938));
+        assert_eq!(items[0].z, 20.0);
+        assert!(items[0].param4.is_nan());
+        assert_eq!((items[1].x, items[1].y), (125000, -32500));
+        assert_eq!((items[2].x, items[2].y), (7, 9));
+        assert_eq!(items[2].param2, 5.0);
+    }
+
+    #[test]
+    fn mission_items_resolve_jump_ids() {
+        let mut loop_start = simple_item(3, 16, json!([0, 0, 0, 0, 47.0, 8.0, 20]));
+        loop_start["doJumpId"] = json!(7);
+        let mut jump = simple_item(2, CMD_DO_JUMP, json!([7, 3, 0, 0, 0, 0, 0]));
+        jump["doJumpId"] = json!(2);
+        let plan = json!({ "mission": { "items": [loop_start, jump] } });
+        let items = mission_items(&plan).unwrap();
+        assert_eq!(items[1].param1, 0.0);
+
+        let mut missing = plan.clone();
+        missing["mission"]["items"][1]["params"][0] = json!(9);
+        assert!(mission_items(&missing).is_err());
+    }
+}
@@ -0,0 +1,338 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// SRTM marks missing samples with this value
+const SRTM_VOID: i16 = -32768;
+
+// TIFF tags read from GeoTIFF DEM tiles
+const TAG_IMAGE_WIDTH: u16 = 256;
+const TAG_IMAGE_LENGTH: u16 = 257;
+const TAG_BITS_PER_SAMPLE: u16 = 258;
+const TAG_COMPRESSION: u16 = 259;
+const TAG_STRIP_OFFSETS: u16 = 273;
+const TAG_STRIP_BYTE_COUNTS: u16 = 279;
+const TAG_SAMPLE_FORMAT: u16 = 339;
+const TAG_MODEL_PIXEL_SCALE: u16 = 33550;
+const TAG_MODEL_TIEPOINT: u16 = 33922;
+const TAG_GDAL_NODATA: u16 = 42113;
+
+// Tile is a regular grid of elevations in meters AMSL, stored in rows from north to south
+struct Tile {
+    // position of the first sample
+    north: f64,
+    west: f64,
+    // spacing between samples in degrees
+    lat_step: f64,
+    lon_step: f64,
+    rows: usize,
+    cols: usize,
+    data: Vec<Option<f64>>,
+}
+
+impl Tile {
+    // elevation interpolates the 4 surrounding samples, None when outside or on a void
+    fn elevation(&self, lat: f64, lon: f64) -> Option<f64> {
+        let row = (self.north - lat) / self.lat_step;
+        let col = (lon - self.west) / self.lon_step;
+        if row < 0.0 || col < 0.0 || row > (self.rows - 1) as f64 || col > (self.cols - 1) as f64 {
+            return None;
+        }
+        let (r0, c0) = (row.floor() as usize, col.floor() as usize);
+        let (r1, c1) = ((r0 + 1).min(self.rows - 1), (c0 + 1).min(self.cols - 1));
+        let (fr, fc) = (row - r0 as f64, col - c0 as f64);
+        let sample = |r: usize, c: usize| self.data[r * self.cols + c];
+        let top = sample(r0, c0)? * (1.0 - fc) + sample(r0, c1)? * fc;
+        let bottom = sample(r1, c0)? * (1.0 - fc) + sample(r1, c1)? * fc;
+        Some(top * (1.0 - fr) + bottom * fr)
+    }
+
+    // load_hgt reads an SRTM tile, named after its south west corner such as N37W122.hgt
+    fn load_hgt(path: &Path) -> io::Result<Tile> {
+        let invalid = |msg: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), msg),
+            )
+        };
+        let name = path
+            .file_stem()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| invalid("Invalid SRTM tile name"))?
+            .to_uppercase();
+        let lon_start = name
+            .find(['E', 'W'])
+            .ok_or_else(|| invalid("Invalid SRTM tile name"))?;
+        let mut south: f64 = name[1..lon_start]
+            .parse()
+            .map_err(|_| invalid("Invalid SRTM tile name"))?;
+        let mut west: f64 = name[lon_start + 1..]
+            .parse()
+            .map_err(|_| invalid("Invalid SRTM tile name"))?;
+        if name.starts_with('S') {
+            south = -south;
+        }
+        if &name[lon_start..lon_start + 1] == "W" {
+            west = -west;
+        }
+
+        let bytes = fs::read(path)?;
+        let size = ((bytes.len() / 2) as f64).sqrt() as usize;
+        if size < 2 || size * size * 2 != bytes.len() {
+            return Err(invalid("Unexpected SRTM tile size"));
+        }
+        let data = bytes
+            .chunks(2)
+            .map(|b| match i16::from_be_bytes([b[0], b[1]]) {
+                SRTM_VOID => None,
+                value => Some(value as f64),
+            })
+            .collect();
+        let step = 1.0 / (size - 1) as f64;
+        Ok(Tile {
+            north: south + 1.0,
+            west,
+            lat_step: step,
+            lon_step: step,
+            rows: size,
+            cols: size,
+            data,
+        })
+    }
+
+    // load_geotiff reads a single band, uncompressed, strip based GeoTIFF in WGS84 degrees
+    fn load_geotiff(path: &Path) -> io::Result<Tile> {
+        let bytes = fs::read(path)?;
+        let tiff = TiffReader::new(&bytes).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: Not a TIFF file", path.display()),
+            )
+        })?;
+        tiff.to_tile().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{}: Only uncompressed, strip based GeoTIFF DEMs are supported",
+                    path.display()
+                ),
+            )
+        })
+    }
+}
+
+// TiffReader reads the tags of the first image in a TIFF file
+struct TiffReader<'a> {
+    bytes: &'a [u8],
+    little_endian: bool,
+    // (tag, type, count, value or offset)
+    entries: Vec<(u16, u16, u32, u32)>,
+    // offset of the value field of each entry, used when the value fits inline
+    inline: Vec<usize>,
+}
+
+impl<'a> TiffReader<'a> {
+    fn new(bytes: &'a [u8]) -> Option<TiffReader<'a>> {
+        let little_endian = match bytes.get(0..2)? {
+            b"II" => true,
+            b"MM" => false,
+            _ => return None,
+        };
+        let mut tiff = TiffReader {
+            bytes,
+            little_endian,
+            entries: vec![],
+            inline: vec![],
+        };
+        let ifd = tiff.u32_at(4)? as usize;
+        let count = tiff.u16_at(ifd)? as usize;
+        for i in 0..count {
+            let entry = ifd + 2 + i * 12;
+            tiff.entries.push((
+                tiff.u16_at(entry)?,
+                tiff.u16_at(entry + 2)?,
+                tiff.u32_at(entry + 4)?,
+                tiff.u32_at(entry + 8)?,
+            ));
+            tiff.inline.push(entry + 8);
+        }
+        Some(tiff)
+    }
+
+    fn u16_at(&self, offset: usize) -> Option<u16> {
+        let b = self.bytes.get(offset..offset + 2)?;
+        Some(if self.little_endian {
+            u16::from_le_bytes([b[0], b[1]])
+        } else {
+            u16::from_be_bytes([b[0], b[1]])
+        })
+    }
+
+    fn u32_at(&self, offset: usize) -> Option<u32> {
+        let b: [u8; 4] = self.bytes.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if self.little_endian {
+            u32::from_le_bytes(b)
+        } else {
+            u32::from_be_bytes(b)
+        })
+    }
+
+    fn f64_at(&self, offset: usize) -> Option<f64> {
+        let b: [u8; 8] = self.bytes.get(offset..offset + 8)?.try_into().ok()?;
+        Some(if self.little_endian {
+            f64::from_le_bytes(b)
+        } else {
+            f64::from_be_bytes(b)
+        })
+    }
+
+    // values reads a SHORT, LONG or DOUBLE tag as a list of numbers
+    fn values(&self, tag: u16) -> Option<Vec<f64>> {
+        let index = self.entries.iter().position(|e| e.0 == tag)?;
+        let (_, kind, count, offset) = self.entries[index];
+        let size = match kind {
+            3 => 2,
+            4 => 4,
+            12 => 8,
+            _ => return None,
+        };
+        let start = if size * count as usize <= 4 {
+            self.inline[index]
+        } else {
+            offset as usize
+        };
+        (0..count as usize)
+            .map(|i| match kind {
+                3 => self.u16_at(start + i * 2).map(|v| v as f64),
+                4 => self.u32_at(start + i * 4).map(|v| v as f64),
+                _ => self.f64_at(start + i * 8),
+            })
+            .collect()
+    }
+
+    // ascii reads an ASCII tag
+    fn ascii(&self, tag: u16) -> Option<String> {
+        let index = self.entries.iter().position(|e| e.0 == tag && e.1 == 2)?;
+        let (_, _, count, offset) = self.entries[index];
+        let start = if count <= 4 {
+            self.inline[index]
+        } else {
+            offset as usize
+        };
+        let text = self.bytes.get(start..start + count as usize)?;
+        Some(
+            String::from_utf8_lossy(text)
+                .trim_end_matches('\0')
+                .to_string(),
+        )
+    }
+
+    fn to_tile(&self) -> Option<Tile> {
+        let cols = *self.values(TAG_IMAGE_WIDTH)?.first()? as usize;
+        let rows = *self.values(TAG_IMAGE_LENGTH)?.first()? as usize;
+        let bits = *self.values(TAG_BITS_PER_SAMPLE)?.first()? as usize;
+        let format = self
+            .values(TAG_SAMPLE_FORMAT)
+            .and_then(|v| v.first().copied())
+            .unwrap_or(1.0) as u16;
+        let compression = self
+            .values(TAG_COMPRESSION)
+            .and_then(|v| v.first().copied())
+            .unwrap_or(1.0);
+        if compression != 1.0 || rows < 2 || cols < 2 {
+            return None;
+        }
+        let scale = self.values(TAG_MODEL_PIXEL_SCALE)?;
+        let tiepoint = self.values(TAG_MODEL_TIEPOINT)?;
+        if scale.len() < 2 || tiepoint.len() < 6 {
+            return None;
+        }
+        let nodata: Option<f64> = self
+            .ascii(TAG_GDAL_NODATA)
+            .and_then(|v| v.trim().parse().ok());
+
+        // strips hold consecutive rows, so the samples can be read in order across strips
+        let bytes_per_sample = bits / 8;
+        let offsets = self.values(TAG_STRIP_OFFSETS)?;
+        let byte_counts = self.values(TAG_STRIP_BYTE_COUNTS)?;
+        let mut data = Vec::with_capacity(rows * cols);
+        for (strip, byte_count) in offsets.iter().zip(byte_counts.iter()) {
+            let start = *strip as usize;
+            for i in 0..(*byte_count as usize / bytes_per_sample) {
+                let offset = start + i * bytes_per_sample;
+                let value = match (format, bits) {
+                    (1, 16) => self.u16_at(offset)? as f64,
+                    (2, 16) => self.u16_at(offset)? as i16 as f64,
+                    (2, 32) => self.u32_at(offset)? as i32 as f64,
+                    (3, 32) => f32::from_bits(self.u32_at(offset)?) as f64,
+                    _ => return None,
+                };
+                data.push(if Some(value) == nodata {
+                    None
+                } else {
+                    Some(value)
+                });
+            }
+        }
+        if data.len() != rows * cols {
+            return None;
+        }
+
+        // the tiepoint is the outer corner of the first pixel, samples are at pixel centers
+        Some(Tile {
+            north: tiepoint[4] - scale[1] / 2.0,
+            west: tiepoint[3] + scale[0] / 2.0,
+            lat_step: scale[1],
+            lon_step: scale[0],
+            rows,
+            cols,
+            data,
+        })
+    }
+}
+
+// Terrain looks up ground elevation from a set of local DEM tiles
+#[derive(Default)]
+pub struct Terrain {
+    tiles: Vec<Tile>,
+}
+
+impl Terrain {
+    // load reads a single DEM tile, or every .hgt, .tif and .tiff tile in a directory
+    pub fn load(path: &Path) -> io::Result<Terrain> {
+        let mut terrain = Terrain::default();
+        if path.is_dir() {
+            for entry in fs::read_dir(path)? {
+                terrain.load_tile(&entry?.path())?;
+            }
+        } else {
+            terrain.load_tile(path)?;
+        }
+        if terrain.tiles.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No DEM tiles found in {}", path.display()),
+            ));
+        }
+        Ok(terrain)
+    }
+
+    fn load_tile(&mut self, path: &Path) -> io::Result<()> {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+        match extension.as_str() {
+            "hgt" => self.tiles.push(Tile::load_hgt(path)?),
+            "tif" | "tiff" => self.tiles.push(Tile::load_geotiff(path)?),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // elevation returns the ground elevation in meters AMSL, None when no tile covers it
+    pub fn elevation(&self, lat: f64, lon: f64) -> Option<f64> {
+        self.tiles.iter().find_map(|tile| tile.elevation(lat, lon))
+    }
+}
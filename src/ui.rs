@@ -32,7 +32,7 @@ pub fn draw<B: Backend>(f: &mut Frame<B>, app: &App) {
     draw_overview(f, app, chunks[1]);
 }
 
-fn draw_overview<B: Backend>(f: &mut Frame<B>, app: &mut App, area: Rect) {
+fn draw_overview<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
     let chunks = Layout::default()
         .constraints([Constraint::Percentage(80), Constraint::Percentage(20)].as_ref())
         .split(area);
@@ -40,7 +40,7 @@ fn draw_overview<B: Backend>(f: &mut Frame<B>, app: &mut App, area: Rect) {
     draw_overview_text(f, app, chunks[1]);
 }
 
-fn draw_overview_gauges<B: Backend>(f: &mut Frame<B>, app: &mut App, area: Rect) {
+fn draw_overview_gauges<B: Backend>(f: &mut Frame<B>, _app: &App, area: Rect) {
     let mut constraint = Vec::new();
     for _ in 0..=8 {
         constraint.push(Constraint::Ratio(1, 8));
@@ -66,7 +66,7 @@ fn draw_overview_gauges<B: Backend>(f: &mut Frame<B>, app: &mut App, area: Rect)
     }
 }
 
-fn draw_overview_text<B: Backend>(f: &mut Frame<B>, app: &mut App, area: Rect) {
+fn draw_overview_text<B: Backend>(f: &mut Frame<B>, _app: &App, area: Rect) {
     let text = vec![
         Spans::from(
             "Registered Vehicles: udp://:14540 udp://:14541 udp://:14542 udp://:14543 udp://:14544 udp://:14545 udp://:14546 udp://:14548"
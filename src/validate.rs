@@ -0,0 +1,270 @@
+use serde_json::Value;
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::airspace::{check_path, Airspace};
+use crate::geofence::to_local;
+use crate::terrain::Terrain;
+
+// commands with a position in params 4-6
+const NAV_COMMANDS: [u64; 7] = [16, 17, 18, 19, 21, 22, 31];
+const CMD_WAYPOINT: u64 = 16;
+const CMD_LOITER_TURNS: u64 = 18;
+const CMD_LOITER_TIME: u64 = 19;
+const CMD_RETURN_TO_LAUNCH: u64 = 20;
+const CMD_DO_JUMP: u64 = 177;
+// MAV_FRAME values of the altitude in param 7
+const FRAME_GLOBAL: u64 = 0;
+const FRAME_GLOBAL_TERRAIN_ALT: u64 = 10;
+// limit on the items walked, so a plan that jumps forever still finishes
+const MAX_STEPS: usize = 100_000;
+
+fn read_plan(path: &Path) -> io::Result<Value> {
+    let data = fs::read_to_string(path)?;
+    let plan: Value = serde_json::from_str(data.as_str())?;
+    if !plan["mission"]["items"].is_array() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("No mission items in {}", path.display()),
+        ));
+    }
+    Ok(plan)
+}
+
+// flight_order returns the mission item indices in the order they are flown, following each
+// DO_JUMP back to its target item for its repeat count
+fn flight_order(items: &[Value]) -> Vec<usize> {
+    let mut order = vec![];
+    let mut remaining: HashMap<usize, i64> = HashMap::new();
+    let mut i = 0;
+    while i < items.len() && order.len() < MAX_STEPS {
+        let item = &items[i];
+        if item["command"].as_u64() != Some(CMD_DO_JUMP) {
+            order.push(i);
+            i += 1;
+            continue;
+        }
+        let target_id = item["params"][0].as_u64();
+        let target = items
+            .iter()
+            .position(|target| target["doJumpId"].as_u64() == target_id);
+        let left = remaining
+            .entry(i)
+            .or_insert_with(|| item["params"][1].as_i64().unwrap_or(0));
+        match target {
+            Some(target) if *left != 0 => {
+                // a negative repeat count jumps forever
+                if *left > 0 {
+                    *left -= 1;
+                }
+                i = target;
+            }
+            _ => i += 1,
+        }
+    }
+    order
+}
+
+// Altitudes converts the altitude of a mission item in any frame to meters above home
+struct Altitudes<'a> {
+    // planned home elevation in meters AMSL
+    home: f64,
+    terrain: Option<&'a Terrain>,
+}
+
+impl Altitudes<'_> {
+    fn relative(&self, frame: u64, lat: f64, lon: f64, alt: f64) -> f64 {
+        match frame {
+            FRAME_GLOBAL => alt - self.home,
+            // without terrain data the ground is taken to be level with home
+            FRAME_GLOBAL_TERRAIN_ALT => match self.terrain.and_then(|t| t.elevation(lat, lon)) {
+                Some(ground) => ground + alt - self.home,
+                None => alt,
+            },
+            _ => alt,
+        }
+    }
+}
+
+// item_position reads the (lat, lon, alt above home) of a navigation item, None for other items
+fn item_position(item: &Value, altitudes: &Altitudes) -> Option<(f64, f64, f64)> {
+    let command = item["command"].as_u64().unwrap_or(0);
+    if !NAV_COMMANDS.contains(&command) {
+        return None;
+    }
+    let params = &item["params"];
+    let (lat, lon, alt) = (
+        params[4].as_f64()?,
+        params[5].as_f64()?,
+        params[6].as_f64()?,
+    );
+    if lat == 0.0 && lon == 0.0 {
+        return None;
+    }
+    let frame = item["frame"].as_u64().unwrap_or(0);
+    Some((lat, lon, altitudes.relative(frame, lat, lon, alt)))
+}
+
+// plan_path reads the (lat, lon, alt above home) path flown by the mission items of a QGC plan
+fn plan_path(plan: &Value, altitudes: &Altitudes) -> Vec<(f64, f64, f64)> {
+    let items = plan["mission"]["items"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default();
+    flight_order(&items)
+        .into_iter()
+        .filter_map(|i| item_position(&items[i], altitudes))
+        .collect()
+}
+
+// estimate_duration estimates the flight time of a plan in seconds, flying every leg at the
+// plan's hover speed, or cruise speed for fixed wings, and adding holds and loiters
+fn estimate_duration(plan: &Value, altitudes: &Altitudes) -> f64 {
+    let items = plan["mission"]["items"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default();
+    let fixed_wing = matches!(
+        plan["mission"]["vehicleType"].as_u64(),
+        Some(1) | Some(19..=22)
+    );
+    let speed = if fixed_wing {
+        plan["mission"]["cruiseSpeed"].as_f64().unwrap_or(15.0)
+    } else {
+        plan["mission"]["hoverSpeed"].as_f64().unwrap_or(5.0)
+    };
+
+    let mut seconds = 0.0;
+    let mut home = None;
+    let mut last: Option<(f64, f64, f64)> = None;
+    for i in flight_order(&items) {
+        let item = &items[i];
+        let command = item["command"].as_u64().unwrap_or(0);
+        let position = match command {
+            CMD_RETURN_TO_LAUNCH => home,
+            _ => item_position(item, altitudes),
+        };
+        if let Some(position) = position {
+            if let Some(last) = last {
+                let (dx, dy) = to_local((last.0, last.1), (position.0, position.1));
+                seconds += dx.hypot(dy).hypot(position.2 - last.2) / speed;
+            }
+            home.get_or_insert(position);
+            last = Some(position);
+        }
+        let params = &item["params"];
+        match command {
+            CMD_WAYPOINT | CMD_LOITER_TIME => seconds += params[0].as_f64().unwrap_or(0.0),
+            CMD_LOITER_TURNS => {
+                let turns = params[0].as_f64().unwrap_or(0.0);
+                let radius = params[2].as_f64().unwrap_or(0.0).abs();
+                seconds += turns * 2.0 * PI * radius / speed;
+            }
+            _ => {}
+        }
+    }
+    seconds
+}
+
+// validate_plans checks every plan against the airspaces, printing each issue found and the
+// estimated flight time
+// home_elevation is used for plans without a planned home position, terrain for items in the
+// terrain frame
+// returns false when any plan is flagged
+pub fn validate_plans(
+    plans: &[String],
+    airspaces: &[Airspace],
+    home_elevation: f64,
+    terrain: Option<&Terrain>,
+) -> io::Result<bool> {
+    let mut ok = true;
+    for plan in plans {
+        let value = read_plan(Path::new(plan.as_str()))?;
+        let home = value["mission"]["plannedHomePosition"][2]
+            .as_f64()
+            .unwrap_or(home_elevation);
+        let altitudes = Altitudes { home, terrain };
+        let path = plan_path(&value, &altitudes);
+        let seconds = estimate_duration(&value, &altitudes).round() as u64;
+        println!(
+            "{}: estimated flight time {}:{:02}",
+            plan,
+            seconds / 60,
+            seconds % 60
+        );
+        let flags = check_path(airspaces, &path, home);
+        if flags.is_empty() {
+            println!("{}: ok", plan);
+        }
+        for flag in flags {
+            println!("{}: {}", plan, flag);
+            ok = false;
+        }
+    }
+    Ok(ok)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn waypoint(frame: u64, alt: f64) -> Value {
+        json!({"command": 16, "frame": frame, "params": [0, 0, 0, null, 47.0, 8.0, alt]})
+    }
+
+    fn jump(target: u64, repeat: i64) -> Value {
+        json!({"command": CMD_DO_JUMP, "params": [target, repeat, 0, 0, 0, 0, 0]})
+    }
+
+    #[test]
+    fn flight_order_repeats_jumps() {
+        let mut items = vec![
+            waypoint(3, 10.0),
+            waypoint(3, 10.0),
+            jump(2, 2),
+            waypoint(3, 10.0),
+        ];
+        items[1]["doJumpId"] = json!(2);
+        assert_eq!(flight_order(&items), vec![0, 1, 1, 1, 3]);
+    }
+
+    #[test]
+    fn flight_order_stops_endless_jumps() {
+        let mut items = vec![waypoint(3, 10.0), jump(1, -1)];
+        items[0]["doJumpId"] = json!(1);
+        assert_eq!(flight_order(&items).len(), MAX_STEPS);
+        // a jump to a missing item is skipped
+        assert_eq!(flight_order(&[jump(7, 3), waypoint(3, 10.0)]), vec![1]);
+    }
+
+    #[test]
+    fn item_position_converts_altitude_to_above_home() {
+        let altitudes = Altitudes {
+            home: 400.0,
+            terrain: None,
+        };
+        let alt = |item: Value| item_position(&item, &altitudes).unwrap().2;
+        assert_eq!(alt(waypoint(3, 50.0)), 50.0);
+        assert_eq!(alt(waypoint(0, 450.0)), 50.0);
+        // terrain frame without terrain data treats the ground as level with home
+        assert_eq!(alt(waypoint(10, 50.0)), 50.0);
+    }
+
+    #[test]
+    fn plan_path_uses_planned_home_for_amsl_items() {
+        let plan = json!({"mission": {
+            "plannedHomePosition": [47.0, 8.0, 500.0],
+            "items": [waypoint(0, 520.0), waypoint(3, 20.0)],
+        }});
+        let altitudes = Altitudes {
+            home: plan["mission"]["plannedHomePosition"][2].as_f64().unwrap(),
+            terrain: None,
+        };
+        let path = plan_path(&plan, &altitudes);
+        assert_eq!(path, vec![(47.0, 8.0, 20.0), (47.0, 8.0, 20.0)]);
+    }
+}